@@ -0,0 +1,39 @@
+-- 事件历史，value 为事件的指标结果（与 JSON 消息中的 value 相同）
+CREATE TABLE events (
+    id uuid NOT NULL,
+    ts timestamptz NOT NULL,
+    symbol text NOT NULL, -- 全市场事件为空
+    period text NOT NULL,
+    event_type text NOT NULL,
+    priority text NOT NULL,
+    severity double precision NOT NULL,
+    suppressed bigint NOT NULL,
+    schema_version integer NOT NULL,
+    value jsonb NOT NULL,
+    PRIMARY KEY (id, ts)
+);
+CREATE INDEX events_symbol_ts ON events (symbol, ts DESC);
+CREATE INDEX events_type_ts ON events (event_type, ts DESC);
+
+-- 收盘k线，同一根k线重复写入时更新
+CREATE TABLE klines (
+    symbol text NOT NULL,
+    interval text NOT NULL,
+    start_ts timestamptz NOT NULL,
+    open double precision NOT NULL,
+    high double precision NOT NULL,
+    low double precision NOT NULL,
+    close double precision NOT NULL,
+    volume double precision NOT NULL,
+    PRIMARY KEY (symbol, interval, start_ts)
+);
+
+-- 安装了 TimescaleDB 时转换为 hypertable
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'timescaledb') THEN
+        PERFORM create_hypertable('events', 'ts', chunk_time_interval => interval '7 days');
+        PERFORM create_hypertable('klines', 'start_ts', chunk_time_interval => interval '30 days');
+    END IF;
+END
+$$;
@@ -0,0 +1,594 @@
+use crate::config::{load_config, Config, ConfigReceiver};
+use crate::consumer::Consumer;
+use crate::dedup::Deduplicator;
+use crate::dispatcher::{dispatcher, EventSender, Output};
+use crate::encoding::Encoding;
+use crate::file_sink::FileSink;
+use crate::helper::assign_worker;
+use crate::kline_store::KlineStore;
+use crate::listing::{
+    exchange_info_refresher, funding_info_refresher, load_exchange_info, ListingTracker,
+};
+use crate::market::market_aggregator;
+use crate::nats::NatsSink;
+use crate::postgres::PostgresSink;
+use crate::proto;
+use crate::pubsub::PubSubSink;
+use crate::redis::RedisSink;
+use crate::reload::config_reloader;
+use crate::server;
+use crate::server::hub::Hub;
+use crate::sink::FanOut;
+use crate::symbols::{FilterReceiver, SymbolFilter};
+use crate::types::{Event, MarkPrice, MarketMessage, Message, SymbolInfo, Ticker};
+use crate::worker::{worker, WorkerContext};
+use anyhow::Result;
+use fast_websocket_client::proxy::Proxy;
+use fast_websocket_client::{ConnectionInitOptions, WebSocketBuilder};
+use prost::Message as _;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+const STREAM_URL: &str = "wss://fstream.binance.com/stream?streams=!ticker@arr/!markPrice@arr";
+// 退出时等待行情连接关闭的时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Called when the WebSocket opens
+async fn handle_open() {
+    info!("[OPEN] WebSocket connection established.");
+}
+
+/// Called when the WebSocket closes
+async fn handle_close() {
+    info!("[CLOSE] WebSocket connection closed.");
+}
+
+async fn handle_error(e: String) {
+    error!("{}", e);
+}
+
+// 只持有 worker 和录制的弱引用，退出时流水线关闭后不再转发
+async fn handle_message(
+    worker_txs: Weak<Vec<tokio::sync::mpsc::Sender<Message>>>,
+    recorder: Option<mpsc::WeakSender<String>>,
+    msg: String,
+) {
+    let Some(worker_txs) = worker_txs.upgrade() else {
+        return;
+    };
+    for message in parse_message(&msg) {
+        let idx = assign_worker(message_symbol(&message), worker_txs.len());
+        let _ = worker_txs[idx].try_send(message);
+    }
+    // 录制原始消息，写不过来时丢弃
+    if let Some(recorder) = recorder.and_then(|r| r.upgrade()) {
+        let _ = recorder.try_send(msg);
+    }
+}
+
+// 解析 combined stream 消息
+fn parse_message(msg: &str) -> Vec<Message> {
+    let Ok(mut json_value) = serde_json::from_str::<serde_json::Value>(msg) else {
+        return Vec::new();
+    };
+    match json_value["stream"].as_str() {
+        Some("!ticker@arr") => {
+            let data = json_value["data"].take();
+            serde_json::from_value::<Vec<Ticker>>(data)
+                .map(|tickers| tickers.into_iter().map(Message::Ticker).collect())
+                .unwrap_or_default()
+        }
+        Some("!markPrice@arr") => {
+            let data = json_value["data"].take();
+            serde_json::from_value::<Vec<MarkPrice>>(data)
+                .map(|mark_prices| mark_prices.into_iter().map(Message::MarkPrice).collect())
+                .unwrap_or_default()
+        }
+        _ => {
+            warn!("未知事件类型: {}", msg);
+            Vec::new()
+        }
+    }
+}
+
+fn message_symbol(message: &Message) -> &str {
+    match message {
+        Message::Ticker(t) => &t.symbol,
+        Message::MarkPrice(m) => &m.symbol,
+        Message::Query(_) | Message::FundingInfo(_) => "",
+    }
+}
+
+// 消息中第一条数据的事件时间，用于按原速回放
+fn message_time(msg: &str) -> Option<u64> {
+    let json_value = serde_json::from_str::<serde_json::Value>(msg).ok()?;
+    json_value["data"][0]["E"].as_u64()
+}
+
+// 运行中的处理流水线
+struct Pipeline {
+    workers: Arc<Vec<mpsc::Sender<Message>>>,
+    market_tx: mpsc::Sender<MarketMessage>,
+    dispatcher: JoinHandle<()>,
+}
+
+// 启动事件分发器、全市场聚合器和 worker pool
+fn start_pipeline(
+    cfg: &Config,
+    output: Output,
+    exchange_info: Option<HashMap<String, SymbolInfo>>,
+    hub: Hub,
+    filter_rx: FilterReceiver,
+    config_rx: ConfigReceiver,
+) -> Pipeline {
+    // 创建事件分发器，所有检测器的事件经过去重后写到各个 sink
+    let sinks = match &output {
+        Output::Sinks(sinks) => sinks.clone(),
+        Output::File(_) => FanOut::default(),
+    };
+    let (event_tx, event_rx) = mpsc::channel::<Event>(10000);
+    let event_tx = EventSender::new(event_tx);
+    let dedup = Deduplicator::new(cfg.dedup.clone());
+    let dispatcher_config = config_rx.clone();
+    let dispatcher_hub = hub.clone();
+    let dispatcher_filter = filter_rx.clone();
+    let dispatcher = tokio::spawn(async move {
+        info!("🚀 Event dispatcher started");
+        dispatcher(
+            event_rx,
+            dedup,
+            dispatcher_config,
+            output,
+            dispatcher_hub,
+            dispatcher_filter,
+        )
+        .await;
+    });
+
+    // 创建全市场聚合器
+    let listing_tracker = ListingTracker::new(exchange_info, &cfg.listing);
+    let (market_tx, market_rx) = mpsc::channel::<MarketMessage>(10000);
+    let market_config = config_rx.clone();
+    let events = event_tx.clone();
+    tokio::spawn(async move {
+        info!("🚀 Market aggregator started");
+        market_aggregator(market_rx, market_config, listing_tracker, events).await;
+    });
+
+    // 创建 worker pool
+    let mut worker_txs = Vec::new();
+    for i in 0..cfg.server.worker_count as usize {
+        let (tx, rx) = mpsc::channel::<Message>(10000);
+        worker_txs.push(tx);
+        let ctx = WorkerContext {
+            market_tx: market_tx.clone(),
+            events: event_tx.clone(),
+            hub: hub.clone(),
+            filter: filter_rx.clone(),
+            config: config_rx.clone(),
+            sinks: sinks.clone(),
+        };
+        let max_kline_count = cfg.server.max_kline_count;
+        tokio::spawn(async move {
+            info!("🚀 Worker {} started", i);
+            worker(rx, max_kline_count, ctx).await;
+        });
+    }
+
+    Pipeline {
+        workers: Arc::new(worker_txs),
+        market_tx,
+        dispatcher,
+    }
+}
+
+fn symbol_filter(cfg: &Config) -> Result<SymbolFilter> {
+    SymbolFilter::new(cfg.symbols.clone())
+        .map_err(|e| anyhow::anyhow!("invalid [symbols] config: {}", e))
+}
+
+// 启动所有 sink 的写出任务，都不等待下游连接成功
+fn spawn_sinks(cfg: &Config) -> Result<(FanOut, Vec<JoinHandle<()>>)> {
+    let mut sinks = FanOut::default();
+    let mut tasks = Vec::new();
+    // redis 不可用时事件先缓冲，后台重连
+    let (redis_sink, task) = RedisSink::spawn(cfg)?;
+    sinks.push(redis_sink);
+    tasks.push(task);
+    // pub/sub 发布，sinks.pubsub.enabled 可以热加载，第一次发布时才连接
+    let (pubsub_sink, task) = PubSubSink::spawn(cfg)?;
+    sinks.push(pubsub_sink);
+    tasks.push(task);
+    // 收盘k线写入 redis，sinks.klines.enabled 可以热加载
+    let (kline_store, task) = KlineStore::spawn(cfg)?;
+    sinks.push(kline_store);
+    tasks.push(task);
+    // 配置了 [postgres] 时保存事件和k线历史，第一次写入时才连接
+    if let Some(postgres) = &cfg.postgres {
+        let (sink, task) = PostgresSink::spawn(postgres)?;
+        sinks.push(sink);
+        tasks.push(task);
+    }
+    // 配置了 [files] 时写到本地文件，按天切分
+    if let Some(files) = &cfg.files {
+        let (sink, task) = FileSink::spawn(files)?;
+        sinks.push(sink);
+        tasks.push(task);
+    }
+    // 配置了 [nats] 时发布到 JetStream，第一次发布时才连接
+    if let Some(nats) = &cfg.nats {
+        let (sink, task) = NatsSink::spawn(nats)?;
+        sinks.push(sink);
+        tasks.push(task);
+    }
+    Ok((sinks, tasks))
+}
+
+// ========== run：实时监控 ==========
+pub async fn run(
+    cfg: Config,
+    config_path: String,
+    record: Option<&Path>,
+    set_log_level: impl Fn(&str) -> Result<()> + Send + 'static,
+) -> Result<()> {
+    let (sinks, sink_tasks) = spawn_sinks(&cfg)?;
+    let sink_metrics = sinks.metrics();
+
+    // 推送中心，保存最近事件并推送给 WebSocket 客户端
+    let hub = match &cfg.api {
+        Some(api_cfg) => Hub::new(api_cfg.broadcast_capacity, api_cfg.recent_events),
+        None => Hub::new(1, 0),
+    };
+
+    // 交易对过滤和检测器开关，管理接口修改后实时生效
+    let (filter_tx, filter_rx) = watch::channel(Arc::new(symbol_filter(&cfg)?));
+    let filter_tx = Arc::new(filter_tx);
+
+    // 热加载的配置，阈值、资金费率、sink、日志级别等修改后实时生效
+    let (config_tx, config_rx) = watch::channel(Arc::new(cfg.clone()));
+    tokio::spawn(config_reloader(
+        config_path,
+        config_tx,
+        filter_tx.clone(),
+        set_log_level,
+    ));
+
+    // 加载 exchangeInfo 快照，用于识别新上线的交易对
+    let exchange_info =
+        match load_exchange_info(&cfg.listing.exchange_info, cfg.proxy.as_ref()).await {
+            Ok(symbols) => {
+                info!("exchangeInfo loaded: {} symbols", symbols.len());
+                Some(symbols)
+            }
+            Err(e) => {
+                error!(
+                    "failed to load exchangeInfo, new listing detection disabled: {:?}",
+                    e
+                );
+                None
+            }
+        };
+
+    let pipeline = start_pipeline(
+        &cfg,
+        Output::Sinks(sinks),
+        exchange_info,
+        hub.clone(),
+        filter_rx,
+        config_rx,
+    );
+    // 这些任务持有流水线的发送端，退出时先停止
+    let mut background = vec![
+        tokio::spawn(exchange_info_refresher(
+            cfg.listing.clone(),
+            cfg.proxy.clone(),
+            pipeline.market_tx.clone(),
+        )),
+        // 回放和回测使用历史数据，只有实时监控加载 fundingInfo
+        tokio::spawn(funding_info_refresher(
+            cfg.listing.clone(),
+            cfg.proxy.clone(),
+            pipeline.workers.clone(),
+        )),
+    ];
+
+    // 配置了 [api] 时启动内置的 HTTP/WebSocket 服务
+    if let Some(api_cfg) = cfg.api.clone() {
+        let workers = pipeline.workers.clone();
+        background.push(tokio::spawn(async move {
+            if let Err(e) = server::serve(api_cfg, hub, workers, filter_tx, sink_metrics).await {
+                error!("api server error: {:?}", e);
+            }
+        }));
+    }
+
+    let (recorder, recorder_task) = match record {
+        Some(path) => {
+            let (tx, task) = spawn_recorder(path).await?;
+            (Some(tx), Some(task))
+        }
+        None => (None, None),
+    };
+
+    let ws = if let Some(proxy_cfg) = &cfg.proxy {
+        let options = ConnectionInitOptions::default().proxy(Some(Proxy::Socks5 {
+            addr: proxy_cfg.addr.clone(),
+            auth: None,
+        }));
+        WebSocketBuilder::new().with_options(options)
+    } else {
+        WebSocketBuilder::new()
+    };
+
+    let worker_txs = Arc::downgrade(&pipeline.workers);
+    let weak_recorder = recorder.as_ref().map(|r| r.downgrade());
+    let client = ws
+        .on_open(move |_| handle_open())
+        .on_close(|_| handle_close())
+        .on_error(handle_error)
+        .on_message(move |message| {
+            handle_message(worker_txs.clone(), weak_recorder.clone(), message)
+        })
+        .connect(STREAM_URL)
+        .await?;
+
+    shutdown_signal().await;
+    // 关闭行情连接，断线重连期间连接任务不会退出，回调只持有弱引用
+    client.close().await;
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, client.await_shutdown())
+        .await
+        .is_err()
+    {
+        warn!(
+            "websocket did not close in {:?}, continuing shutdown",
+            SHUTDOWN_TIMEOUT
+        );
+    }
+    for task in background {
+        task.abort();
+        let _ = task.await;
+    }
+    drop(recorder);
+    if let Some(task) = recorder_task {
+        let _ = task.await;
+    }
+    // 等 worker 和分发器处理完已收到的消息，之后所有 sink 被释放
+    finish(pipeline).await;
+    info!("waiting for sinks to flush");
+    for task in sink_tasks {
+        let _ = task.await;
+    }
+    info!("shutdown complete");
+    Ok(())
+}
+
+// 等待 SIGINT 或 SIGTERM
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            error!("failed to listen for SIGTERM: {:?}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            info!("received SIGINT, shutting down");
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("received SIGINT, shutting down"),
+        _ = terminate.recv() => info!("received SIGTERM, shutting down"),
+    }
+}
+
+// 录制原始行情消息，每行一条
+async fn spawn_recorder(path: &Path) -> Result<(mpsc::Sender<String>, JoinHandle<()>)> {
+    let file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    info!("recording market data to {}", path.display());
+    let (tx, mut rx) = mpsc::channel::<String>(10000);
+    let task = tokio::spawn(async move {
+        let mut writer = tokio::io::BufWriter::new(file);
+        while let Some(msg) = rx.recv().await {
+            let line = format!("{}\n", msg);
+            if let Err(e) = writer.write_all(line.as_bytes()).await {
+                error!("failed to record market data: {:?}", e);
+                break;
+            }
+            // 队列空了再刷盘，避免每条消息一次系统调用
+            if rx.is_empty() {
+                let _ = writer.flush().await;
+            }
+        }
+        let _ = writer.flush().await;
+    });
+    Ok((tx, task))
+}
+
+// 把录制文件中的消息按顺序送给 worker，speed 为 0 时不等待
+async fn feed(path: &Path, speed: f64, workers: &[mpsc::Sender<Message>]) -> Result<usize> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut lines = BufReader::new(file).lines();
+    let started = Instant::now();
+    let mut first_time: Option<u64> = None;
+    let mut count = 0;
+    while let Some(line) = lines.next_line().await? {
+        if speed > 0.0 {
+            if let Some(time) = message_time(&line) {
+                let first = *first_time.get_or_insert(time);
+                let due =
+                    Duration::from_secs_f64(time.saturating_sub(first) as f64 / 1000.0 / speed);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+        for message in parse_message(&line) {
+            let idx = assign_worker(message_symbol(&message), workers.len());
+            // 回放时不丢消息，等 worker 处理
+            if workers[idx].send(message).await.is_err() {
+                return Ok(count);
+            }
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+// 回放完成后关闭流水线，等事件分发器处理完剩余事件
+async fn finish(pipeline: Pipeline) {
+    let Pipeline {
+        workers,
+        market_tx,
+        dispatcher,
+    } = pipeline;
+    drop(workers);
+    drop(market_tx);
+    let _ = dispatcher.await;
+}
+
+// ========== replay：回放录制的行情，事件照常写到 redis ==========
+pub async fn replay(cfg: Config, file: &Path, speed: f64) -> Result<()> {
+    let (sinks, sink_tasks) = spawn_sinks(&cfg)?;
+    let (_filter_tx, filter_rx) = watch::channel(Arc::new(symbol_filter(&cfg)?));
+    let (_config_tx, config_rx) = watch::channel(Arc::new(cfg.clone()));
+    // 回放的是历史数据，不和当前的 exchangeInfo 比较上新
+    let pipeline = start_pipeline(
+        &cfg,
+        Output::Sinks(sinks),
+        None,
+        Hub::new(1, 0),
+        filter_rx,
+        config_rx,
+    );
+    let count = feed(file, speed, &pipeline.workers).await?;
+    info!("replayed {} messages from {}", count, file.display());
+    finish(pipeline).await;
+    // 等待缓冲中的事件写完
+    for task in sink_tasks {
+        let _ = task.await;
+    }
+    Ok(())
+}
+
+// ========== backtest：离线回测，事件写到文件 ==========
+pub async fn backtest(cfg: Config, file: &Path, output: &Path) -> Result<()> {
+    let writer = BufWriter::new(File::create(output)?);
+    let (_filter_tx, filter_rx) = watch::channel(Arc::new(symbol_filter(&cfg)?));
+    let (_config_tx, config_rx) = watch::channel(Arc::new(cfg.clone()));
+    let pipeline = start_pipeline(
+        &cfg,
+        Output::File(writer),
+        None,
+        Hub::new(1, 0),
+        filter_rx,
+        config_rx,
+    );
+    let count = feed(file, 0.0, &pipeline.workers).await?;
+    finish(pipeline).await;
+    info!(
+        "backtest finished: {} messages from {}, events written to {}",
+        count,
+        file.display(),
+        output.display()
+    );
+    Ok(())
+}
+
+// ========== dump-state：导出运行中实例的内存状态 ==========
+pub async fn dump_state(cfg: &Config, url: Option<String>) -> Result<()> {
+    let base = match url {
+        Some(url) => url,
+        None => {
+            let api = cfg
+                .api
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("[api] is not configured, use --url"))?;
+            format!("http://{}", api.listen.replace("0.0.0.0", "127.0.0.1"))
+        }
+    };
+    let base = base.trim_end_matches('/');
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let mut state = serde_json::Map::new();
+    for (key, path) in [("symbols", "/symbols"), ("recent_events", "/events/recent")] {
+        let value: serde_json::Value = client
+            .get(format!("{}{}", base, path))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        state.insert(key.to_string(), value);
+    }
+    println!("{}", serde_json::to_string_pretty(&state)?);
+    Ok(())
+}
+
+// ========== consume：从 redis 队列消费事件 ==========
+pub async fn consume(
+    cfg: &Config,
+    queue: Option<String>,
+    count: Option<usize>,
+    no_ack: bool,
+    visibility_timeout: u64,
+) -> Result<()> {
+    let queue = queue.unwrap_or_else(|| cfg.sinks.redis.default_queue.clone());
+    let consumer =
+        Consumer::connect(&cfg.redis, &queue, Duration::from_secs(visibility_timeout)).await?;
+    // 每半个 visibility_timeout 检查一次超时未 ack 的消息
+    let requeue_interval = Duration::from_secs(visibility_timeout) / 2;
+    let mut next_requeue = Instant::now();
+    let mut consumed = 0;
+    while count.is_none_or(|count| consumed < count) {
+        if Instant::now() >= next_requeue {
+            let requeued = consumer.requeue_stale().await?;
+            if requeued > 0 {
+                info!("requeued {} stale messages", requeued);
+            }
+            next_requeue = Instant::now() + requeue_interval;
+        }
+        let Some(delivery) = consumer.next(Duration::from_secs(1)).await? else {
+            continue;
+        };
+        let decoded = match delivery.encoding {
+            Encoding::Protobuf => proto::Event::decode(delivery.payload.as_slice())
+                .map(|event| format!("{:?}", event))
+                .map_err(anyhow::Error::from),
+            _ => delivery.event().map(|event| event.to_json()),
+        };
+        // 无法解码的消息移到死信队列，继续消费
+        let text = match decoded {
+            Ok(text) => text,
+            Err(e) => {
+                error!("failed to decode message {}: {:?}", delivery.key, e);
+                if !no_ack {
+                    consumer.dead_letter(&delivery).await?;
+                }
+                continue;
+            }
+        };
+        println!("{}", text);
+        if !no_ack {
+            consumer.ack(&delivery).await?;
+        }
+        consumed += 1;
+    }
+    Ok(())
+}
+
+// ========== check-config：检查配置文件 ==========
+pub fn check_config(path: &str) -> Result<Config> {
+    load_config(path).map_err(|e| anyhow::anyhow!("config load error: {}", e))
+}
@@ -0,0 +1,70 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+// ========== 命令行 ==========
+#[derive(Parser)]
+#[command(name = "perpx", version, about = "Binance 永续合约行情监控")]
+pub struct Cli {
+    /// 配置文件路径
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "PERPX_CONFIG",
+        default_value = "config.toml"
+    )]
+    pub config: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// 连接 Binance 实时监控（默认）
+    Run {
+        /// 把收到的原始行情消息追加写到文件，供 replay/backtest 使用
+        #[arg(long)]
+        record: Option<PathBuf>,
+    },
+    /// 检查配置文件
+    CheckConfig,
+    /// 回放录制的行情，事件照常写到 redis
+    Replay {
+        /// run --record 录制的文件，每行一条原始行情消息
+        file: PathBuf,
+        /// 回放速度倍数，0 表示不等待
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// 用录制的行情离线回测，事件写到 JSON Lines 文件，不连接 redis
+    Backtest {
+        /// run --record 录制的文件，每行一条原始行情消息
+        file: PathBuf,
+        /// 事件输出文件
+        #[arg(short, long, default_value = "events.jsonl")]
+        output: PathBuf,
+    },
+    /// 通过 API 导出运行中实例的内存状态
+    DumpState {
+        /// API 地址，默认使用配置中的 api.listen
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// 从 redis 队列消费事件并打印，用于调试
+    Consume {
+        /// 队列名，即 <namespace>:queue:<queue> 中的 <queue>，默认为 sinks.redis.default_queue
+        queue: Option<String>,
+        /// 消费多少条后退出，默认一直消费
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+        /// 不 ack，消息超过 visibility-timeout 后会重新放回队列
+        #[arg(long)]
+        no_ack: bool,
+        /// 取出后多久没有 ack 视为处理失败（秒）
+        #[arg(long, default_value_t = 30)]
+        visibility_timeout: u64,
+    },
+    /// 输出事件的 JSON Schema
+    Schema,
+}
@@ -1,16 +1,50 @@
-use serde::Deserialize;
+use crate::encoding::Encoding;
+use crate::handlers::trend_handler::MAX_CONSECUTIVE_LOOKBACK;
+use crate::pubsub::check_template;
+use crate::symbols::check_pattern;
+use crate::types::{EventType, Interval, Priority};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::watch;
 
-#[derive(Deserialize, Clone)]
+// 各任务通过该通道获取热加载后的最新配置
+pub type ConfigReceiver = watch::Receiver<Arc<Config>>;
+
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct Config {
+    #[serde(default)]
     pub redis: RedisConfig,
+    #[serde(default)]
     pub server: ServerConfig,
     pub proxy: Option<ProxyConfig>,
+    pub api: Option<ApiConfig>,
+    pub postgres: Option<PostgresConfig>,
+    pub files: Option<FilesConfig>,
+    pub nats: Option<NatsConfig>,
     #[serde(default)]
     pub logging: Logging,
+    #[serde(default)]
     pub funding_rate: FundingRateConfig,
+    #[serde(default)]
+    pub premium: PremiumConfig,
+    #[serde(default)]
+    pub market: MarketConfig,
+    #[serde(default)]
+    pub listing: ListingConfig,
+    #[serde(default)]
+    pub dedup: DedupConfig,
+    #[serde(default)]
+    pub severity: SeverityConfig,
+    #[serde(default)]
+    pub sinks: SinksConfig,
+    #[serde(default)]
+    pub symbols: SymbolsConfig,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct Logging {
     #[serde(default)]
     pub level: String,
@@ -24,29 +58,513 @@ impl Default for Logging {
     }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct RedisConfig {
-    pub host: String,
-    pub port: u16,
+    pub url: Option<String>, // 完整的连接地址，设置后忽略下面的字段，用户名和密码中的特殊字符需要百分号编码
+    pub mode: RedisMode,
+    pub host: String,       // standalone 模式的地址
+    pub port: u16,          // standalone 模式的端口
+    pub nodes: Vec<String>, // sentinel 实例或 cluster 节点，host:port
+    pub master: String,     // sentinel 模式的 master 名称
     pub user: String,
-    pub password: String,
+    pub password: String, // 建议通过环境变量 PERPX_REDIS_PASSWORD 设置
+    pub db: usize,        // 数据库编号，cluster 模式只能为 0
+    pub tls: bool,        // 是否使用 TLS
+    pub sentinel_password: Option<String>, // sentinel 实例自己的密码
+    pub namespace: String, // key 前缀，<namespace>:msg:<uuid>、<namespace>:queue:<name>，多套环境共用 redis 时区分
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            mode: RedisMode::Standalone,
+            host: "127.0.0.1".to_string(),
+            port: 6379,
+            nodes: Vec::new(),
+            master: String::new(),
+            user: String::new(),
+            password: String::new(),
+            db: 0,
+            tls: false,
+            sentinel_password: None,
+            namespace: "perpx".to_string(),
+        }
+    }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisMode {
+    Standalone,
+    Sentinel,
+    Cluster,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct ServerConfig {
-    pub worker_count: u16,
-    pub max_kline_count: u32,
-    pub redis_data_expire: usize,
+    pub worker_count: u16,        // worker 数量
+    pub max_kline_count: u32,     // 每个周期最多保留的k线数量
+    pub redis_data_expire: usize, // redis 消息过期时间，单位秒
 }
 
-#[derive(Deserialize, Clone)]
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            worker_count: 4,
+            max_kline_count: 100,
+            redis_data_expire: 120,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct FundingRateConfig {
     pub min_funding_rate: f64,        // 最小资金费率 0.0001(0.01%)
-    pub min_funding_rate_change: f64, // 资金费率变化的最小幅度 0.0001(0.01%)
+    pub min_funding_rate_change: f64, // 资金费率变化的最小幅度 0.00005(0.005%)
     pub funding_rate_interval: u64,   // 资金费率事件的最小间隔，单位秒
+    pub countdown_minutes: Vec<u64>,  // 结算前多少分钟发送倒计时提醒
+    pub countdown_min_rate: f64,      // 发送倒计时提醒的最小资金费率绝对值 0.0005(0.05%)
+}
+
+impl Default for FundingRateConfig {
+    fn default() -> Self {
+        Self {
+            min_funding_rate: 0.0001,
+            min_funding_rate_change: 0.00005,
+            funding_rate_interval: 600,
+            countdown_minutes: vec![30, 5],
+            countdown_min_rate: 0.0005,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PremiumConfig {
+    pub mark_index_threshold: f64, // 标记价格相对指数价格的溢价阈值 0.005(0.5%)
+    pub last_mark_threshold: f64,  // 最新价相对标记价格的偏离阈值 0.003(0.3%)
+    pub avg_deviation: f64,        // 溢价偏离滚动均值的阈值 0.002(0.2%)
+    pub window: usize,             // 滚动均值的样本数量（markPrice 每秒推送一次）
+    pub premium_interval: u64,     // 同一交易对溢价事件的最小间隔，单位秒
+}
+
+impl Default for PremiumConfig {
+    fn default() -> Self {
+        Self {
+            mark_index_threshold: 0.005,
+            last_mark_threshold: 0.003,
+            avg_deviation: 0.002,
+            window: 300,
+            premium_interval: 300,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct MarketConfig {
+    pub ranking_interval: u64,      // 全市场排行计算间隔，单位秒
+    pub top_n: usize,               // 每个榜单保留的交易对数量
+    pub intervals: Vec<Interval>,   // 参与涨跌幅排行的周期
+    pub breadth_extreme: f64,       // 上涨或下跌交易对占比达到该值视为极端
+    pub min_breadth_symbols: usize, // 计算市场宽度所需的最少交易对数量
+    pub benchmark: String,          // 计算相对强弱的基准交易对
+    pub beta_window: usize,         // 计算 beta 的滚动窗口（k线数量）
+    pub min_beta_samples: usize,    // 计算 beta 所需的最少样本数
+    pub divergence_z: f64,          // 超额收益达到多少倍标准差视为背离
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        Self {
+            ranking_interval: 60,
+            top_n: 10,
+            intervals: Interval::ALL.to_vec(),
+            breadth_extreme: 0.8,
+            min_breadth_symbols: 20,
+            benchmark: "BTCUSDT".to_string(),
+            beta_window: 30,
+            min_beta_samples: 10,
+            divergence_z: 3.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ListingConfig {
+    pub exchange_info: String, // exchangeInfo 地址或本地文件路径
+    pub funding_info: String,  // fundingInfo 地址或本地文件路径，用于确定结算周期，为空表示不加载
+    pub refresh_interval: u64, // exchangeInfo 和 fundingInfo 刷新间隔，单位秒，0 表示不刷新
+    pub delist_after: u64,     // 超过多少秒没有出现在 ticker 中视为下架
+}
+
+impl Default for ListingConfig {
+    fn default() -> Self {
+        Self {
+            exchange_info: "https://fapi.binance.com/fapi/v1/exchangeInfo".to_string(),
+            funding_info: "https://fapi.binance.com/fapi/v1/fundingInfo".to_string(),
+            refresh_interval: 3600,
+            delist_after: 1800,
+        }
+    }
+}
+
+// 交易对过滤和检测器开关，可以通过管理接口在运行时修改
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SymbolsConfig {
+    pub include: Vec<String>, // 只处理匹配的交易对，空表示全部；默认 glob，"re:" 开头为正则
+    pub exclude: Vec<String>, // 排除匹配的交易对，优先于 include
+    pub disabled: HashMap<String, HashSet<EventType>>, // 按交易对关闭的检测器，"*" 表示所有交易对
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct DedupConfig {
+    pub default_cooldown: u64, // 同一(交易对, 事件类型, 周期)的默认冷却时间，单位秒，0 表示不冷却
+    pub cross_interval_window: u64, // 同类事件在更大周期上发出后，多少秒内抑制小周期事件，0 表示不抑制
+    pub rules: HashMap<EventType, DedupRule>, // 按事件类型覆盖
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct DedupRule {
+    pub cooldown: Option<u64>, // 冷却时间，单位秒，不填使用 default_cooldown
+    pub period_cooldown: HashMap<String, u64>, // 按周期覆盖冷却时间，如 { "5m" = 1800 }
+    pub escalation_only: bool, // 冷却期内只有强度（连续次数、振幅倍数等）增加才再次发送
+    pub min_escalation: f64,   // 强度至少增加的比例，0 表示只要增加就发送
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SeverityConfig {
+    pub normal: f64,   // severity 达到该值为 normal，以下为 low
+    pub high: f64,     // severity 达到该值为 high
+    pub critical: f64, // severity 达到该值为 critical
+}
+
+impl Default for SeverityConfig {
+    fn default() -> Self {
+        Self {
+            normal: 1.0,
+            high: 2.0,
+            critical: 4.0,
+        }
+    }
+}
+
+impl SeverityConfig {
+    pub fn priority(&self, severity: f64) -> Priority {
+        if severity >= self.critical {
+            Priority::Critical
+        } else if severity >= self.high {
+            Priority::High
+        } else if severity >= self.normal {
+            Priority::Normal
+        } else {
+            Priority::Low
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SinksConfig {
+    pub redis: RedisSinkConfig,
+    pub pubsub: PubSubSinkConfig,
+    pub klines: KlineSinkConfig,
+    pub postgres: PostgresSinkConfig,
+    pub files: FileSinkConfig,
+    pub nats: NatsSinkConfig,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RedisSinkConfig {
+    pub min_priority: Priority,            // 低于该优先级的事件不写入 redis
+    pub default_queue: String,             // 没有匹配的路由规则和优先级队列时写入的队列
+    pub queues: HashMap<Priority, String>, // 各优先级写入的队列名，未配置的使用 default_queue
+    pub routes: Vec<RouteRule>,            // 按事件类型和交易对路由，按顺序匹配，优先于 queues
+    pub encoding: Encoding,                // 消息编码：json / msgpack / protobuf
+    pub buffer: RedisBufferConfig,         // redis 不可用时的缓冲，修改后需要重启
+}
+
+impl Default for RedisSinkConfig {
+    fn default() -> Self {
+        Self {
+            min_priority: Priority::Low,
+            default_queue: "events".to_string(),
+            queues: HashMap::new(),
+            routes: Vec::new(),
+            encoding: Encoding::Json,
+            buffer: RedisBufferConfig::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct RedisBufferConfig {
+    pub size: usize,                // 内存中最多缓冲的消息数
+    pub spill_path: Option<String>, // 内存缓冲满后写到该文件，不配置时丢弃最早的消息
+    pub spill_max_mb: u64,          // 磁盘缓冲文件的最大大小，超过后丢弃新消息
+    pub batch_size: usize,          // 每批最多写出的消息数
+    pub batch_window_ms: u64,       // 收到消息后等待多久合并成一批写出
+}
+
+impl Default for RedisBufferConfig {
+    fn default() -> Self {
+        Self {
+            size: 10000,
+            spill_path: None,
+            spill_max_mb: 512,
+            batch_size: 200,
+            batch_window_ms: 5,
+        }
+    }
+}
+
+impl RedisSinkConfig {
+    /// 按优先级选择队列，路由规则由分发器匹配
+    pub fn queue_name(&self, priority: Priority) -> &str {
+        self.queues
+            .get(&priority)
+            .map(String::as_str)
+            .unwrap_or(&self.default_queue)
+    }
+}
+
+// redis pub/sub 发布，即发即弃，可以和队列同时使用
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PubSubSinkConfig {
+    pub enabled: bool,
+    pub min_priority: Priority, // 低于该优先级的事件不发布
+    pub channels: Vec<String>,  // 频道模板，可用 {namespace} {type} {symbol} {period} {priority}
+    pub encoding: Encoding,
+}
+
+impl Default for PubSubSinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_priority: Priority::Low,
+            channels: vec![
+                "{namespace}:events:{type}".to_string(),
+                "{namespace}:events:{symbol}".to_string(),
+            ],
+            encoding: Encoding::Json,
+        }
+    }
+}
+
+// 写入 postgres 的内容，需要配置 [postgres]
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PostgresSinkConfig {
+    pub min_priority: Priority, // 低于该优先级的事件不写入
+    pub klines: bool,           // 是否写入收盘k线
+}
+
+impl Default for PostgresSinkConfig {
+    fn default() -> Self {
+        Self {
+            min_priority: Priority::Low,
+            klines: false,
+        }
+    }
+}
+
+// 写入本地文件的内容，需要配置 [files]
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct FileSinkConfig {
+    pub min_priority: Priority, // 低于该优先级的事件不写入
+    pub klines: bool,           // 是否写入收盘k线
+}
+
+impl Default for FileSinkConfig {
+    fn default() -> Self {
+        Self {
+            min_priority: Priority::Low,
+            klines: true,
+        }
+    }
+}
+
+// 发布到 NATS JetStream 的内容，需要配置 [nats]
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct NatsSinkConfig {
+    pub min_priority: Priority, // 低于该优先级的事件不发布
+    pub encoding: Encoding,     // 事件编码，k线固定为 JSON
+    pub klines: bool,           // 是否发布收盘k线
+}
+
+impl Default for NatsSinkConfig {
+    fn default() -> Self {
+        Self {
+            min_priority: Priority::Low,
+            encoding: Encoding::Json,
+            klines: false,
+        }
+    }
+}
+
+// 收盘k线写入 redis 有序集合，保留最近 server.max_kline_count 根
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct KlineSinkConfig {
+    pub enabled: bool,
+    pub intervals: Vec<Interval>, // 写入哪些周期，默认全部
+}
+
+impl Default for KlineSinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intervals: Interval::ALL.to_vec(),
+        }
+    }
+}
+
+// 事件路由规则，event_types 和 symbols 都匹配时写入 queue
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct RouteRule {
+    #[serde(default)]
+    pub event_types: Vec<EventType>, // 为空表示所有事件类型
+    #[serde(default)]
+    pub symbols: Vec<String>, // 交易对 glob，"re:" 开头为正则，为空表示所有交易对
+    pub queue: String,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct ApiConfig {
+    pub listen: String, // 监听地址，如 0.0.0.0:8080
+    #[serde(default = "default_client_buffer")]
+    pub client_buffer: usize, // 每个 WebSocket 客户端的缓冲消息数，写满后断开该客户端
+    #[serde(default = "default_broadcast_capacity")]
+    pub broadcast_capacity: usize, // 推送中心的广播缓冲大小，事件和k线各一个
+    #[serde(default = "default_recent_events")]
+    pub recent_events: usize, // /events/recent 保留的最近事件数
+    #[serde(default = "default_query_timeout")]
+    pub query_timeout: u64, // 查询 worker 状态的超时时间（毫秒）
+    pub admin_token: Option<String>, // 管理接口的 Bearer token，不配置时不开放管理接口
+}
+
+fn default_client_buffer() -> usize {
+    256
+}
+
+fn default_broadcast_capacity() -> usize {
+    8192
+}
+
+fn default_recent_events() -> usize {
+    500
+}
+
+fn default_query_timeout() -> u64 {
+    2000
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct PostgresConfig {
+    pub url: String, // 如 postgres://perpx@localhost/perpx，建议通过环境变量 PERPX_POSTGRES_URL 设置
+    #[serde(default)]
+    pub tls: bool, // 是否使用 TLS，使用 webpki 根证书校验服务端
+    #[serde(default = "default_pg_buffer_size")]
+    pub buffer_size: usize, // postgres 不可用时内存中最多缓冲的记录数
+    #[serde(default = "default_pg_batch_size")]
+    pub batch_size: usize, // 每批最多写入的记录数
+    #[serde(default = "default_pg_batch_window_ms")]
+    pub batch_window_ms: u64, // 收到记录后等待多久合并成一批写入
+}
+
+fn default_pg_buffer_size() -> usize {
+    100000
+}
+
+fn default_pg_batch_size() -> usize {
+    1000
+}
+
+fn default_pg_batch_window_ms() -> u64 {
+    1000
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct FilesConfig {
+    pub dir: String, // 输出目录，事件和k线分别写到 events/ 和 klines/ 子目录
+    #[serde(default)]
+    pub format: FileFormat,
+    #[serde(default)]
+    pub compression: FileCompression,
+    #[serde(default)]
+    pub retention_days: u32, // 删除多少天前的文件，0 表示不删除
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct NatsConfig {
+    pub url: String, // 如 nats://localhost:4222，多个地址用逗号分隔，也可以通过环境变量 PERPX_NATS_URL 设置
+    pub token: Option<String>, // token 认证，建议通过环境变量 PERPX_NATS_TOKEN 设置
+    pub credentials_file: Option<String>, // .creds 文件，用于 JWT 认证
+    #[serde(default = "default_nats_stream")]
+    pub stream: String, // JetStream stream 名称
+    #[serde(default = "default_nats_subject_prefix")]
+    pub subject_prefix: String, // 主题前缀，stream 收集 <prefix>.> 下的所有主题
+    #[serde(default = "default_nats_create_stream")]
+    pub create_stream: bool, // stream 不存在时是否自动创建，关闭时需要预先创建
+    #[serde(default = "default_nats_max_age_hours")]
+    pub max_age_hours: u64, // 自动创建的 stream 保留多少小时，0 表示不限制
+    #[serde(default = "default_nats_buffer_size")]
+    pub buffer_size: usize, // nats 不可用时内存中最多缓冲的消息数
+}
+
+fn default_nats_stream() -> String {
+    "PERPX".to_string()
+}
+
+fn default_nats_subject_prefix() -> String {
+    "perpx".to_string()
+}
+
+fn default_nats_create_stream() -> bool {
+    true
+}
+
+fn default_nats_max_age_hours() -> u64 {
+    72
+}
+
+fn default_nats_buffer_size() -> usize {
+    100000
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    #[default]
+    Jsonl,
+    Parquet, // 需要编译时开启 parquet feature
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileCompression {
+    #[default]
+    None,
+    Gzip,
+    Snappy, // 只用于 parquet
+    Zstd,   // 只用于 parquet
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct ProxyConfig {
     pub addr: String,
 }
@@ -54,8 +572,609 @@ pub struct ProxyConfig {
 // 方便主程序直接调用一个函数加载配置
 use std::fs;
 
+impl Config {
+    /// 与旧配置相比发生变化、需要重启才能生效的配置项
+    pub fn restart_required(&self, old: &Config) -> Vec<&'static str> {
+        [
+            ("redis", self.redis != old.redis),
+            ("server", self.server != old.server),
+            ("proxy", self.proxy != old.proxy),
+            ("api", self.api != old.api),
+            ("postgres", self.postgres != old.postgres),
+            ("files", self.files != old.files),
+            ("nats", self.nats != old.nats),
+            ("listing", self.listing != old.listing),
+            (
+                "sinks.redis.buffer",
+                self.sinks.redis.buffer != old.sinks.redis.buffer,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+
+    /// 与旧配置相比发生变化、可以直接生效的配置项
+    pub fn live_changes(&self, old: &Config) -> Vec<&'static str> {
+        [
+            ("logging", self.logging != old.logging),
+            ("funding_rate", self.funding_rate != old.funding_rate),
+            ("premium", self.premium != old.premium),
+            ("market", self.market != old.market),
+            ("dedup", self.dedup != old.dedup),
+            ("severity", self.severity != old.severity),
+            ("sinks", self.sinks != old.sinks),
+            ("symbols", self.symbols != old.symbols),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+
+    /// 需要重启的配置项保留旧值，其余使用新值
+    pub fn merge_live(&self, old: &Config) -> Config {
+        Config {
+            redis: old.redis.clone(),
+            server: old.server.clone(),
+            proxy: old.proxy.clone(),
+            api: old.api.clone(),
+            postgres: old.postgres.clone(),
+            files: old.files.clone(),
+            nats: old.nats.clone(),
+            listing: old.listing.clone(),
+            sinks: SinksConfig {
+                redis: RedisSinkConfig {
+                    buffer: old.sinks.redis.buffer.clone(),
+                    ..self.sinks.redis.clone()
+                },
+                ..self.sinks.clone()
+            },
+            ..self.clone()
+        }
+    }
+}
+
 pub fn load_config(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
     let content = fs::read_to_string(path)?;
-    let config: Config = toml::from_str(&content)?;
+    let mut config: Config = toml::from_str(&content)?;
+    apply_env(&mut config)?;
+    config.validate()?;
     Ok(config)
 }
+
+// 配置校验错误，一次列出所有问题
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid config value(s):", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// 收集校验失败的配置项
+#[derive(Default)]
+struct Checker {
+    errors: Vec<String>,
+}
+
+impl Checker {
+    fn check(&mut self, ok: bool, path: impl fmt::Display, message: impl fmt::Display) {
+        if !ok {
+            self.errors.push(format!("{}: {}", path, message));
+        }
+    }
+
+    // 注意 NaN 也不满足条件
+    fn positive(&mut self, value: f64, path: &str) {
+        self.check(value > 0.0, path, format!("must be > 0, got {}", value));
+    }
+
+    fn non_negative(&mut self, value: f64, path: &str) {
+        self.check(value >= 0.0, path, format!("must be >= 0, got {}", value));
+    }
+
+    fn at_least(&mut self, value: u64, min: u64, path: &str) {
+        self.check(
+            value >= min,
+            path,
+            format!("must be >= {}, got {}", min, value),
+        );
+    }
+}
+
+impl Config {
+    /// 校验取值范围，返回所有不合法的配置项
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut c = Checker::default();
+
+        let redis = &self.redis;
+        if redis.url.is_none() {
+            match redis.mode {
+                RedisMode::Standalone => {
+                    c.check(!redis.host.is_empty(), "redis.host", "must not be empty")
+                }
+                RedisMode::Sentinel | RedisMode::Cluster => c.check(
+                    !redis.nodes.is_empty(),
+                    "redis.nodes",
+                    format!("must not be empty in {:?} mode", redis.mode).to_lowercase(),
+                ),
+            }
+            c.check(
+                redis.mode != RedisMode::Sentinel || !redis.master.is_empty(),
+                "redis.master",
+                "must not be empty in sentinel mode",
+            );
+            c.check(
+                redis.mode != RedisMode::Cluster || redis.db == 0,
+                "redis.db",
+                "must be 0 in cluster mode",
+            );
+        }
+        c.check(
+            !redis.namespace.is_empty() && !redis.namespace.contains(char::is_whitespace),
+            "redis.namespace",
+            "must not be empty or contain whitespace",
+        );
+        if let Err(e) = crate::redis::client_config(redis) {
+            c.check(
+                false,
+                if redis.url.is_some() {
+                    "redis.url"
+                } else {
+                    "redis"
+                },
+                e,
+            );
+        }
+
+        let server = &self.server;
+        c.at_least(server.worker_count as u64, 1, "server.worker_count");
+        c.at_least(
+            server.max_kline_count as u64,
+            MAX_CONSECUTIVE_LOOKBACK as u64,
+            "server.max_kline_count",
+        );
+        c.at_least(
+            server.redis_data_expire as u64,
+            1,
+            "server.redis_data_expire",
+        );
+
+        if let Some(api) = &self.api {
+            c.check(!api.listen.is_empty(), "api.listen", "must not be empty");
+            c.at_least(api.client_buffer as u64, 1, "api.client_buffer");
+            c.at_least(api.broadcast_capacity as u64, 1, "api.broadcast_capacity");
+            c.at_least(api.query_timeout, 1, "api.query_timeout");
+            c.check(
+                api.admin_token.as_ref().is_none_or(|t| !t.is_empty()),
+                "api.admin_token",
+                "must not be empty",
+            );
+        }
+        if let Some(postgres) = &self.postgres {
+            c.check(
+                !postgres.url.is_empty(),
+                "postgres.url",
+                "must not be empty",
+            );
+            if let Err(e) = postgres.url.parse::<tokio_postgres::Config>() {
+                c.check(false, "postgres.url", e);
+            }
+            c.at_least(postgres.buffer_size as u64, 1, "postgres.buffer_size");
+            c.at_least(postgres.batch_size as u64, 1, "postgres.batch_size");
+        }
+        if let Some(files) = &self.files {
+            c.check(!files.dir.is_empty(), "files.dir", "must not be empty");
+            c.check(
+                cfg!(feature = "parquet") || files.format != FileFormat::Parquet,
+                "files.format",
+                "parquet requires building with --features parquet",
+            );
+            c.check(
+                files.format == FileFormat::Parquet
+                    || matches!(
+                        files.compression,
+                        FileCompression::None | FileCompression::Gzip
+                    ),
+                "files.compression",
+                format!(
+                    "{:?} is only supported for parquet, use none or gzip for jsonl",
+                    files.compression
+                )
+                .to_lowercase(),
+            );
+        }
+        if let Some(nats) = &self.nats {
+            c.check(!nats.url.is_empty(), "nats.url", "must not be empty");
+            c.check(
+                !nats.stream.is_empty()
+                    && !nats
+                        .stream
+                        .contains(|ch: char| ch.is_whitespace() || ".*>/\\".contains(ch)),
+                "nats.stream",
+                format!("invalid stream name {:?}", nats.stream),
+            );
+            c.check(
+                !nats.subject_prefix.is_empty()
+                    && nats.subject_prefix.split('.').all(|token| {
+                        !token.is_empty()
+                            && !token.contains(|ch: char| ch.is_whitespace() || "*>".contains(ch))
+                    }),
+                "nats.subject_prefix",
+                format!("invalid subject prefix {:?}", nats.subject_prefix),
+            );
+            c.check(
+                nats.token.as_ref().is_none_or(|t| !t.is_empty()),
+                "nats.token",
+                "must not be empty",
+            );
+            c.at_least(nats.buffer_size as u64, 1, "nats.buffer_size");
+        }
+        if let Some(proxy) = &self.proxy {
+            c.check(!proxy.addr.is_empty(), "proxy.addr", "must not be empty");
+        }
+        c.check(
+            tracing_subscriber::EnvFilter::try_new(&self.logging.level).is_ok(),
+            "logging.level",
+            format!("invalid filter {:?}", self.logging.level),
+        );
+
+        let funding = &self.funding_rate;
+        c.non_negative(funding.min_funding_rate, "funding_rate.min_funding_rate");
+        c.non_negative(
+            funding.min_funding_rate_change,
+            "funding_rate.min_funding_rate_change",
+        );
+        c.non_negative(
+            funding.countdown_min_rate,
+            "funding_rate.countdown_min_rate",
+        );
+        for (i, minutes) in funding.countdown_minutes.iter().enumerate() {
+            c.at_least(
+                *minutes,
+                1,
+                &format!("funding_rate.countdown_minutes[{}]", i),
+            );
+        }
+
+        let premium = &self.premium;
+        c.positive(premium.mark_index_threshold, "premium.mark_index_threshold");
+        c.positive(premium.last_mark_threshold, "premium.last_mark_threshold");
+        c.positive(premium.avg_deviation, "premium.avg_deviation");
+        c.at_least(premium.window as u64, 1, "premium.window");
+
+        let market = &self.market;
+        c.at_least(market.ranking_interval, 1, "market.ranking_interval");
+        c.at_least(market.top_n as u64, 1, "market.top_n");
+        c.check(
+            market.breadth_extreme > 0.5 && market.breadth_extreme <= 1.0,
+            "market.breadth_extreme",
+            format!("must be in (0.5, 1], got {}", market.breadth_extreme),
+        );
+        c.at_least(
+            market.min_breadth_symbols as u64,
+            1,
+            "market.min_breadth_symbols",
+        );
+        c.check(
+            !market.benchmark.is_empty(),
+            "market.benchmark",
+            "must not be empty",
+        );
+        c.at_least(market.beta_window as u64, 2, "market.beta_window");
+        c.check(
+            market.min_beta_samples >= 2 && market.min_beta_samples <= market.beta_window,
+            "market.min_beta_samples",
+            format!(
+                "must be in [2, market.beta_window={}], got {}",
+                market.beta_window, market.min_beta_samples
+            ),
+        );
+        c.positive(market.divergence_z, "market.divergence_z");
+
+        c.check(
+            !self.listing.exchange_info.is_empty(),
+            "listing.exchange_info",
+            "must not be empty",
+        );
+
+        // 按事件类型排序，错误信息的顺序保持稳定
+        let mut rules = self
+            .dedup
+            .rules
+            .iter()
+            .map(|(event_type, rule)| (format!("dedup.rules.{:?}", event_type), rule))
+            .collect::<Vec<_>>();
+        rules.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, rule) in rules {
+            c.non_negative(rule.min_escalation, &format!("{}.min_escalation", path));
+            // escalation_only 只在冷却期内生效，没有冷却时间时不起作用
+            c.check(
+                !rule.escalation_only
+                    || rule.cooldown.unwrap_or(self.dedup.default_cooldown) > 0
+                    || rule.period_cooldown.values().any(|&cooldown| cooldown > 0),
+                format!("{}.escalation_only", path),
+                "requires cooldown, period_cooldown or dedup.default_cooldown > 0",
+            );
+            for period in rule.period_cooldown.keys() {
+                c.check(
+                    Interval::from_str(period).is_ok(),
+                    format!("{}.period_cooldown.{}", path, period),
+                    "unknown interval, expected one of 5m/15m/1h/4h",
+                );
+            }
+        }
+
+        let severity = &self.severity;
+        c.non_negative(severity.normal, "severity.normal");
+        c.check(
+            severity.high >= severity.normal,
+            "severity.high",
+            format!(
+                "must be >= severity.normal={}, got {}",
+                severity.normal, severity.high
+            ),
+        );
+        c.check(
+            severity.critical >= severity.high,
+            "severity.critical",
+            format!(
+                "must be >= severity.high={}, got {}",
+                severity.high, severity.critical
+            ),
+        );
+
+        let buffer = &self.sinks.redis.buffer;
+        c.at_least(buffer.size as u64, 1, "sinks.redis.buffer.size");
+        c.at_least(buffer.batch_size as u64, 1, "sinks.redis.buffer.batch_size");
+        if buffer.spill_path.is_some() {
+            c.at_least(buffer.spill_max_mb, 1, "sinks.redis.buffer.spill_max_mb");
+        }
+
+        c.check(
+            !self.sinks.redis.default_queue.is_empty(),
+            "sinks.redis.default_queue",
+            "must not be empty",
+        );
+        for (i, route) in self.sinks.redis.routes.iter().enumerate() {
+            c.check(
+                !route.queue.is_empty(),
+                format!("sinks.redis.routes[{}].queue", i),
+                "must not be empty",
+            );
+            for (j, pattern) in route.symbols.iter().enumerate() {
+                if let Some(message) = pattern_error(pattern) {
+                    c.check(
+                        false,
+                        format!("sinks.redis.routes[{}].symbols[{}]", i, j),
+                        message,
+                    );
+                }
+            }
+        }
+        let pubsub = &self.sinks.pubsub;
+        c.check(
+            !pubsub.enabled || !pubsub.channels.is_empty(),
+            "sinks.pubsub.channels",
+            "must not be empty when enabled",
+        );
+        for (i, channel) in pubsub.channels.iter().enumerate() {
+            if let Err(e) = check_template(channel) {
+                c.check(false, format!("sinks.pubsub.channels[{}]", i), e);
+            }
+        }
+        for (priority, queue) in &self.sinks.redis.queues {
+            c.check(
+                !queue.is_empty(),
+                format!("sinks.redis.queues.{:?}", priority).to_lowercase(),
+                "must not be empty",
+            );
+        }
+
+        for (field, patterns) in [
+            ("include", &self.symbols.include),
+            ("exclude", &self.symbols.exclude),
+        ] {
+            for (i, pattern) in patterns.iter().enumerate() {
+                if let Some(message) = pattern_error(pattern) {
+                    c.check(false, format!("symbols.{}[{}]", field, i), message);
+                }
+            }
+        }
+
+        if c.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(c.errors))
+        }
+    }
+}
+
+// 交易对匹配规则的错误，正则的错误信息是多行的，合并成一行
+fn pattern_error(pattern: &str) -> Option<String> {
+    let e = check_pattern(pattern).err()?.to_string();
+    Some(e.lines().map(str::trim).collect::<Vec<_>>().join(" "))
+}
+
+// 环境变量覆盖配置文件，密码等敏感信息不用写到文件里
+fn apply_env(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
+    if let Ok(url) = std::env::var("PERPX_REDIS_URL") {
+        config.redis.url = Some(url);
+    }
+    if let Ok(host) = std::env::var("PERPX_REDIS_HOST") {
+        config.redis.host = host;
+    }
+    if let Ok(port) = std::env::var("PERPX_REDIS_PORT") {
+        config.redis.port = port
+            .parse()
+            .map_err(|e| format!("invalid PERPX_REDIS_PORT: {}", e))?;
+    }
+    if let Ok(user) = std::env::var("PERPX_REDIS_USER") {
+        config.redis.user = user;
+    }
+    if let Ok(password) = std::env::var("PERPX_REDIS_PASSWORD") {
+        config.redis.password = password;
+    }
+    if let Ok(namespace) = std::env::var("PERPX_REDIS_NAMESPACE") {
+        config.redis.namespace = namespace;
+    }
+    if let Ok(url) = std::env::var("PERPX_POSTGRES_URL") {
+        if let Some(postgres) = config.postgres.as_mut() {
+            postgres.url = url;
+        }
+    }
+    if let Ok(url) = std::env::var("PERPX_NATS_URL") {
+        if let Some(nats) = config.nats.as_mut() {
+            nats.url = url;
+        }
+    }
+    if let Ok(token) = std::env::var("PERPX_NATS_TOKEN") {
+        if let Some(nats) = config.nats.as_mut() {
+            nats.token = Some(token);
+        }
+    }
+    if let Ok(token) = std::env::var("PERPX_ADMIN_TOKEN") {
+        if let Some(api) = config.api.as_mut() {
+            api.admin_token = Some(token);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Config {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn reload_changes() {
+        let old = parse(
+            r#"
+            [redis]
+            host = "10.0.0.1"
+            [premium]
+            window = 10
+            "#,
+        );
+        let new = parse(
+            r#"
+            [redis]
+            host = "10.0.0.2"
+            [premium]
+            window = 20
+            [sinks.redis.buffer]
+            size = 100
+            "#,
+        );
+
+        assert_eq!(
+            new.restart_required(&old),
+            vec!["redis", "sinks.redis.buffer"]
+        );
+        assert_eq!(new.live_changes(&old), vec!["premium", "sinks"]);
+        assert!(new.restart_required(&new).is_empty());
+        assert!(new.live_changes(&new).is_empty());
+
+        // 需要重启的配置项保留旧值，其余使用新值
+        let merged = new.merge_live(&old);
+        assert_eq!(merged.redis.host, "10.0.0.1");
+        assert!(merged.sinks.redis.buffer == old.sinks.redis.buffer);
+        assert_eq!(merged.premium.window, 20);
+        assert!(merged.restart_required(&old).is_empty());
+        assert_eq!(merged.live_changes(&old), vec!["premium"]);
+    }
+
+    #[test]
+    fn reload_keeps_sink_routes() {
+        let old = parse("");
+        let new = parse(
+            r#"
+            [sinks.redis]
+            default_queue = "other"
+            [sinks.redis.buffer]
+            batch_size = 10
+            "#,
+        );
+
+        let merged = new.merge_live(&old);
+        assert_eq!(merged.sinks.redis.default_queue, "other");
+        assert_eq!(
+            merged.sinks.redis.buffer.batch_size,
+            old.sinks.redis.buffer.batch_size
+        );
+    }
+
+    #[test]
+    fn example_config_is_valid() {
+        parse(include_str!("../config.toml.example"))
+            .validate()
+            .unwrap();
+        parse("").validate().unwrap();
+    }
+
+    #[test]
+    fn validate_reports_all_errors() {
+        let config = parse(
+            r#"
+            [server]
+            worker_count = 0
+            [premium]
+            window = 0
+            mark_index_threshold = -0.1
+            [market]
+            breadth_extreme = 0.5
+            beta_window = 10
+            min_beta_samples = 20
+            [severity]
+            normal = 2.0
+            high = 1.0
+            [symbols]
+            include = ["re:("]
+            [dedup.rules.ConsecutiveMove]
+            period_cooldown = { "2m" = 60 }
+            [dedup.rules.VolatilitySpike]
+            escalation_only = true
+            "#,
+        );
+        let errors = config.validate().unwrap_err().0;
+        let paths: Vec<_> = errors
+            .iter()
+            .map(|e| e.split(':').next().unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "server.worker_count",
+                "premium.mark_index_threshold",
+                "premium.window",
+                "market.breadth_extreme",
+                "market.min_beta_samples",
+                "dedup.rules.ConsecutiveMove.period_cooldown.2m",
+                "dedup.rules.VolatilitySpike.escalation_only",
+                "severity.high",
+                "symbols.include[0]",
+            ]
+        );
+        // 正则的多行错误信息合并成一行
+        assert!(errors.iter().all(|e| !e.contains('\n')));
+    }
+
+    #[test]
+    fn validate_rejects_nan() {
+        let mut config = parse("");
+        config.market.divergence_z = f64::NAN;
+        config.funding_rate.min_funding_rate = f64::NAN;
+        let errors = config.validate().unwrap_err().0;
+        assert_eq!(errors.len(), 2);
+    }
+}
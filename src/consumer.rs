@@ -0,0 +1,309 @@
+use crate::config::RedisConfig;
+use crate::encoding::Encoding;
+use crate::helper::now_ms;
+use crate::redis::client_config;
+use crate::types::Event;
+use anyhow::Result;
+use rustis::client::{BatchPreparedCommand, Client};
+use rustis::commands::{
+    BlockingCommands, GenericCommands, LMoveWhere, ListCommands, SortedSetCommands, StringCommands,
+    ZAddCondition, ZAddOptions, ZRangeOptions, ZRangeSortBy,
+};
+use rustis::resp::{cmd, BulkString};
+use std::time::Duration;
+use tracing::warn;
+
+// ========== 队列消费 ==========
+// 写入协议：消息内容在 <namespace>:msg:[<encoding>:]<uuid>（带 TTL），key 被 RPUSH 到 <namespace>:queue:<name>
+// 消费协议：
+//   1. BLMOVE 把 key 从队列头部原子地移到 <namespace>:processing:<name>，并在 <namespace>:inflight:<name> 中记录处理期限
+//   2. GET 读取消息内容
+//   3. 处理完成后 ack：从 processing 中移除 key，删除消息内容
+//   4. 超过期限未 ack 的 key（消费者崩溃或卡住）由 requeue_stale 放回队列头部重新投递
+//   5. 无法处理的消息用 dead_letter 移到 <namespace>:dead:<name>，消息内容不再过期，需要人工检查后删除
+// 每条消息至少投递一次，消费者需要能处理重复消息
+// cluster 模式下 BLMOVE 的两个 list 需要在同一个 slot，队列名要用 hash tag，如 {events}
+
+// 从 processing 中移除成功才放回队列，多个消费者同时执行时不会重复放回
+const REQUEUE_SCRIPT: &str = r#"
+redis.call('ZREM', KEYS[3], ARGV[1])
+if redis.call('LREM', KEYS[1], 1, ARGV[1]) > 0 then
+    redis.call('LPUSH', KEYS[2], ARGV[1])
+    return 1
+end
+return 0
+"#;
+
+pub struct Consumer {
+    client: Client,
+    message_prefix: String,
+    queue: String,
+    processing: String,
+    inflight: String,
+    dead: String,
+    visibility_timeout: Duration,
+}
+
+// 取到的一条消息
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub key: String,
+    pub encoding: Encoding,
+    pub payload: Vec<u8>,
+}
+
+impl Delivery {
+    /// 解码为事件，protobuf 编码的消息用 proto::Event 解码 payload
+    pub fn event(&self) -> Result<Event> {
+        self.encoding.decode(&self.payload)
+    }
+}
+
+impl Consumer {
+    /// 连接 redis 并消费队列 <namespace>:queue:<queue>，namespace 使用配置中的 redis.namespace
+    /// visibility_timeout: 取出后多久没有 ack 视为处理失败，由 requeue_stale 放回队列
+    pub async fn connect(
+        cfg: &RedisConfig,
+        queue: &str,
+        visibility_timeout: Duration,
+    ) -> Result<Self> {
+        let client = Client::connect(client_config(cfg)?).await?;
+        Ok(Self::new(client, &cfg.namespace, queue, visibility_timeout))
+    }
+
+    /// 使用已有的连接，BLMOVE 会阻塞该连接上的其他命令，不要和其他用途共用
+    pub fn new(client: Client, namespace: &str, queue: &str, visibility_timeout: Duration) -> Self {
+        Self {
+            client,
+            message_prefix: format!("{}:msg:", namespace),
+            queue: format!("{}:queue:{}", namespace, queue),
+            processing: format!("{}:processing:{}", namespace, queue),
+            inflight: format!("{}:inflight:{}", namespace, queue),
+            dead: format!("{}:dead:{}", namespace, queue),
+            visibility_timeout,
+        }
+    }
+
+    // 消息 key 中的编码：<namespace>:msg:<uuid> 为 JSON，<namespace>:msg:<encoding>:<uuid> 为其他编码
+    fn key_encoding(&self, key: &str) -> Encoding {
+        key.strip_prefix(&self.message_prefix)
+            .and_then(|rest| rest.split_once(':'))
+            .and_then(|(name, _)| Encoding::from_name(name))
+            .unwrap_or_default()
+    }
+
+    fn deadline(&self) -> f64 {
+        (now_ms() + self.visibility_timeout.as_millis() as u64) as f64
+    }
+
+    /// 取下一条消息，timeout 内队列为空时返回 None
+    /// 消息内容已经过期（超过 redis_data_expire 没有被消费）的 key 直接丢弃
+    pub async fn next(&self, timeout: Duration) -> Result<Option<Delivery>> {
+        loop {
+            let key: Option<String> = self
+                .client
+                .blmove(
+                    &self.queue,
+                    &self.processing,
+                    LMoveWhere::Left,
+                    LMoveWhere::Right,
+                    timeout.as_secs_f64(),
+                )
+                .await?;
+            let Some(key) = key else {
+                return Ok(None);
+            };
+            self.client
+                .zadd(
+                    &self.inflight,
+                    (self.deadline(), key.as_str()),
+                    ZAddOptions::default(),
+                )
+                .await?;
+            let payload: Option<BulkString> = self.client.get(&key).await?;
+            match payload {
+                Some(payload) => {
+                    return Ok(Some(Delivery {
+                        encoding: self.key_encoding(&key),
+                        key,
+                        payload: payload.to_vec(),
+                    }))
+                }
+                None => {
+                    warn!("message expired before it was consumed: {}", key);
+                    self.remove(&key).await?;
+                }
+            }
+        }
+    }
+
+    /// 处理完成，移除 key 并删除消息内容
+    pub async fn ack(&self, delivery: &Delivery) -> Result<()> {
+        self.remove(&delivery.key).await?;
+        // 消息内容和队列可能不在同一个 slot，单独删除
+        self.client.del(&delivery.key).await?;
+        Ok(())
+    }
+
+    /// 无法处理的消息移到死信队列，保留消息内容
+    pub async fn dead_letter(&self, delivery: &Delivery) -> Result<()> {
+        let mut transaction = self.client.create_transaction();
+        transaction
+            .lrem(&self.processing, 1, &delivery.key)
+            .forget();
+        transaction.zrem(&self.inflight, &delivery.key).forget();
+        transaction.rpush(&self.dead, &delivery.key).forget();
+        transaction.execute::<()>().await?;
+        // 消息内容和队列可能不在同一个 slot，单独处理
+        self.client.persist(&delivery.key).await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        let mut transaction = self.client.create_transaction();
+        transaction.lrem(&self.processing, 1, key).forget();
+        transaction.zrem(&self.inflight, key).forget();
+        transaction.execute::<()>().await?;
+        Ok(())
+    }
+
+    /// 把超过期限没有 ack 的消息放回队列头部，返回放回的数量
+    pub async fn requeue_stale(&self) -> Result<usize> {
+        // 消费者在 BLMOVE 之后、记录期限之前崩溃时，processing 中的 key 没有期限，这里补上
+        let processing: Vec<String> = self.client.lrange(&self.processing, 0, -1).await?;
+        for key in &processing {
+            self.client
+                .zadd(
+                    &self.inflight,
+                    (self.deadline(), key.as_str()),
+                    ZAddOptions::default().condition(ZAddCondition::NX),
+                )
+                .await?;
+        }
+
+        let stale: Vec<String> = self
+            .client
+            .zrange(
+                &self.inflight,
+                "-inf".to_string(),
+                now_ms().to_string(),
+                ZRangeOptions::default().sort_by(ZRangeSortBy::ByScore),
+            )
+            .await?;
+        let mut count = 0;
+        for key in stale {
+            let requeued: usize = self
+                .client
+                .send(
+                    cmd("EVAL")
+                        .arg(REQUEUE_SCRIPT)
+                        .arg(3)
+                        .arg(&self.processing)
+                        .arg(&self.queue)
+                        .arg(&self.inflight)
+                        .arg(&key),
+                    None,
+                )
+                .await?
+                .to()?;
+            count += requeued;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::RedisQueue;
+    use crate::spool::Pending;
+    use uuid::Uuid;
+
+    // 需要 redis，地址通过 PERPX_TEST_REDIS_URL 指定：cargo test -- --ignored
+    async fn connect() -> Client {
+        let url = std::env::var("PERPX_TEST_REDIS_URL")
+            .unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        Client::connect(url).await.unwrap()
+    }
+
+    async fn enqueue(namespace: &str, message: &[u8]) {
+        let queue = RedisQueue::new(connect().await, namespace, 60, true);
+        let item = Pending {
+            queue_name: "events".to_string(),
+            message: message.to_vec(),
+            encoding: Encoding::Json,
+        };
+        queue.push_batch([&item]).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn requeue_stale() {
+        let namespace = format!("perpx-test-{}", Uuid::new_v4());
+        enqueue(&namespace, b"{}").await;
+        let consumer = Consumer::new(connect().await, &namespace, "events", Duration::ZERO);
+        let delivery = consumer
+            .next(Duration::from_secs(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(delivery.payload, b"{}");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // 多个消费者同时放回时只放回一次
+        let other = Consumer::new(connect().await, &namespace, "events", Duration::ZERO);
+        let (a, b) = tokio::join!(consumer.requeue_stale(), other.requeue_stale());
+        assert_eq!(a.unwrap() + b.unwrap(), 1);
+        assert_eq!(consumer.requeue_stale().await.unwrap(), 0);
+
+        let redelivered = consumer
+            .next(Duration::from_secs(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(redelivered.key, delivery.key);
+        consumer.ack(&redelivered).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(consumer.requeue_stale().await.unwrap(), 0);
+        assert!(consumer
+            .next(Duration::from_secs(1))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dead_letter() {
+        let namespace = format!("perpx-test-{}", Uuid::new_v4());
+        enqueue(&namespace, b"not json").await;
+        let consumer = Consumer::new(
+            connect().await,
+            &namespace,
+            "events",
+            Duration::from_secs(60),
+        );
+        let delivery = consumer
+            .next(Duration::from_secs(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(delivery.event().is_err());
+        consumer.dead_letter(&delivery).await.unwrap();
+
+        let dead: Vec<String> = consumer.client.lrange(&consumer.dead, 0, -1).await.unwrap();
+        assert_eq!(dead, vec![delivery.key.clone()]);
+        assert_eq!(consumer.client.ttl(&delivery.key).await.unwrap(), -1);
+        let processing: Vec<String> = consumer
+            .client
+            .lrange(&consumer.processing, 0, -1)
+            .await
+            .unwrap();
+        assert!(processing.is_empty());
+        consumer
+            .client
+            .del([&delivery.key, &consumer.dead])
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,377 @@
+use crate::config::{DedupConfig, DedupRule};
+use crate::types::{Event, EventPayload, EventType, Interval};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tracing::debug;
+
+// 有周期的事件先等待一段时间（毫秒），同一k线边界上各周期的检测器并发运行，
+// 到齐后从大周期到小周期依次去重，跨周期抑制不受到达顺序影响
+const CROSS_INTERVAL_HOLD: u64 = 1000;
+
+// 上次发出的事件
+struct Emitted {
+    time: u64,       // 发出时间（毫秒）
+    magnitude: f64,  // 事件强度
+    suppressed: u64, // 之后被抑制的次数
+}
+
+// ========== 事件去重 ==========
+// 位于检测器和 sink 之间，按 (交易对, 事件类型, 周期) 做冷却、升级判断和跨周期抑制
+pub struct Deduplicator {
+    config: DedupConfig,
+    last: HashMap<(String, EventType, String), Emitted>,
+    suppressed: HashMap<EventType, u64>, // 上次统计后被抑制的事件数
+    pending: Vec<(u64, Event)>,          // 等待中的事件和到达时间
+}
+
+impl Deduplicator {
+    pub fn new(config: DedupConfig) -> Self {
+        Self {
+            config,
+            last: HashMap::new(),
+            suppressed: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// 热加载配置，保留已有的冷却记录
+    pub fn set_config(&mut self, config: DedupConfig) {
+        self.config = config;
+    }
+
+    /// 加入事件，now 为当前时间（毫秒），之后通过 ready 取出通过去重的事件
+    pub fn push(&mut self, event: Event, now: u64) {
+        self.pending.push((now, event));
+    }
+
+    /// 取出等待期已过并且通过去重的事件，没有周期的事件不等待
+    pub fn ready(&mut self, now: u64) -> Vec<Event> {
+        let hold = if self.config.cross_interval_window > 0 {
+            CROSS_INTERVAL_HOLD
+        } else {
+            0
+        };
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.sort_by_key(|(arrived, _)| *arrived);
+        let mut due = Vec::new();
+        let mut held = Vec::new();
+        let mut batch_start = None;
+        for (arrived, event) in pending {
+            if Interval::from_str(&event.period).is_err() {
+                due.push((arrived, event));
+                continue;
+            }
+            // 从第一个事件开始 hold 毫秒内到达的事件为一批，整批到齐后再去重
+            let start = match batch_start {
+                Some(start) if arrived < start + hold => start,
+                _ => {
+                    batch_start = Some(arrived);
+                    arrived
+                }
+            };
+            if now >= start.saturating_add(hold) {
+                due.push((start, event));
+            } else {
+                held.push((arrived, event));
+            }
+        }
+        self.pending = held;
+        // 同一批内大周期优先，小周期的事件会被跨周期抑制
+        due.sort_by_key(|(start, event)| {
+            let seconds = Interval::from_str(&event.period).map_or(0, |i| i.seconds());
+            (*start, Reverse(seconds))
+        });
+        due.into_iter()
+            .filter_map(|(time, mut event)| self.check(&mut event, time).then_some(event))
+            .collect()
+    }
+
+    /// 取出所有等待中的事件，回测结束时使用
+    pub fn flush(&mut self) -> Vec<Event> {
+        self.ready(u64::MAX)
+    }
+
+    /// 判断事件是否应该发出，now 为当前时间（毫秒）
+    /// 发出时如果之前有被抑制的同类事件，在 suppressed 中附带数量
+    fn check(&mut self, event: &mut Event, now: u64) -> bool {
+        let magnitude = magnitude(event);
+
+        if self.cross_interval_suppressed(event, now) {
+            return self.suppress(event);
+        }
+
+        let key = (
+            event.symbol.clone(),
+            event.event_type(),
+            event.period.clone(),
+        );
+        let rule = self.config.rules.get(&event.event_type());
+        let cooldown = self.cooldown(event.event_type(), rule, &event.period) * 1000;
+        let escalation = rule.filter(|r| r.escalation_only);
+
+        if let Some(last) = self.last.get_mut(&key) {
+            if now.saturating_sub(last.time) < cooldown {
+                let escalated = escalation
+                    .is_some_and(|r| magnitude > last.magnitude * (1.0 + r.min_escalation));
+                if !escalated {
+                    last.suppressed += 1;
+                    return self.suppress(event);
+                }
+            }
+            event.suppressed = last.suppressed;
+        }
+
+        self.last.insert(
+            key,
+            Emitted {
+                time: now,
+                magnitude,
+                suppressed: 0,
+            },
+        );
+        true
+    }
+
+    /// 取出上次统计后被抑制的事件数
+    pub fn take_suppressed(&mut self) -> HashMap<EventType, u64> {
+        std::mem::take(&mut self.suppressed)
+    }
+
+    /// 清理已经过了冷却期的记录
+    pub fn prune(&mut self, now: u64) {
+        let max_cooldown = self
+            .config
+            .rules
+            .values()
+            .flat_map(|r| {
+                r.cooldown
+                    .into_iter()
+                    .chain(r.period_cooldown.values().copied())
+            })
+            .chain([
+                self.config.default_cooldown,
+                self.config.cross_interval_window,
+            ])
+            .max()
+            .unwrap_or(0)
+            * 1000;
+        self.last
+            .retain(|_, e| now.saturating_sub(e.time) < max_cooldown);
+    }
+
+    fn suppress(&mut self, event: &Event) -> bool {
+        debug!(
+            "event suppressed: {} {:?} {}",
+            event.symbol,
+            event.event_type(),
+            event.period
+        );
+        *self.suppressed.entry(event.event_type()).or_default() += 1;
+        false
+    }
+
+    // 全市场排行是按 market.ranking_interval 定期发出的快照，所有排行共用一个 key，
+    // 不使用 default_cooldown，否则冷却时间不小于排行间隔时会被一直抑制
+    fn cooldown(&self, event_type: EventType, rule: Option<&DedupRule>, period: &str) -> u64 {
+        let default = match event_type {
+            EventType::MarketRanking => 0,
+            _ => self.config.default_cooldown,
+        };
+        rule.and_then(|r| r.period_cooldown.get(period).copied().or(r.cooldown))
+            .unwrap_or(default)
+    }
+
+    // 同一交易对同类事件刚在不小于当前周期的其他周期上发出过
+    fn cross_interval_suppressed(&self, event: &Event, now: u64) -> bool {
+        let window = self.config.cross_interval_window * 1000;
+        let Ok(interval) = Interval::from_str(&event.period) else {
+            return false;
+        };
+        if window == 0 {
+            return false;
+        }
+        Interval::ALL
+            .into_iter()
+            .filter(|other| *other != interval && other.seconds() >= interval.seconds())
+            .any(|other| {
+                let key = (event.symbol.clone(), event.event_type(), other.to_string());
+                self.last
+                    .get(&key)
+                    .is_some_and(|e| now.saturating_sub(e.time) < window)
+            })
+    }
+}
+
+// 事件强度，用于升级判断
+// 使用检测器的原始指标，不用按成交额加权后的 severity，避免成交额变化触发升级
+fn magnitude(event: &Event) -> f64 {
+    let magnitude = match &event.payload {
+        EventPayload::ConsecutiveMove(v) => v.count as f64,
+        EventPayload::VolatilitySpike(v) if v.avg_amplitude > 0.0 => v.amplitude / v.avg_amplitude,
+        EventPayload::FundingRate(v) => v.funding_rate,
+        EventPayload::FundingCountdown(v) => v.funding_rate,
+        EventPayload::PremiumDeviation(v) => v.premium,
+        EventPayload::BtcDivergence(v) => v.z_score,
+        EventPayload::MarketBreadth(v) => v.up_ratio.max(v.down_ratio),
+        _ => 0.0,
+    };
+    magnitude.abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ConsecutiveMove, EventPayload, MarketRanking};
+
+    fn config() -> DedupConfig {
+        DedupConfig {
+            default_cooldown: 600,
+            cross_interval_window: 300,
+            rules: HashMap::new(),
+        }
+    }
+
+    fn event(period: &str, count: u32) -> Event {
+        event_with_turnover(period, count, 1e8)
+    }
+
+    fn event_with_turnover(period: &str, count: u32, turnover: f64) -> Event {
+        let payload = EventPayload::ConsecutiveMove(ConsecutiveMove {
+            count,
+            turnover,
+            direction: 1,
+        });
+        Event::new(
+            "BTCUSDT".into(),
+            period.into(),
+            payload,
+            0,
+            count as f64 / 3.0 * turnover / 1e8,
+        )
+        .unwrap()
+    }
+
+    fn periods(events: &[Event]) -> Vec<&str> {
+        events.iter().map(|e| e.period.as_str()).collect()
+    }
+
+    #[test]
+    fn cross_interval_larger_first() {
+        let mut dedup = Deduplicator::new(config());
+        dedup.push(event("1h", 3), 10_000);
+        dedup.push(event("5m", 3), 10_200);
+        assert!(dedup.ready(10_500).is_empty());
+        assert_eq!(periods(&dedup.ready(11_000)), ["1h"]);
+        assert_eq!(dedup.take_suppressed()[&EventType::ConsecutiveMove], 1);
+    }
+
+    #[test]
+    fn cross_interval_smaller_first() {
+        let mut dedup = Deduplicator::new(config());
+        dedup.push(event("5m", 3), 10_000);
+        dedup.push(event("1h", 3), 10_200);
+        assert_eq!(periods(&dedup.ready(11_000)), ["1h"]);
+        // 窗口内之后的小周期事件也被抑制
+        dedup.push(event("15m", 4), 60_000);
+        assert!(dedup.flush().is_empty());
+    }
+
+    #[test]
+    fn cross_interval_separate_batches() {
+        let mut dedup = Deduplicator::new(config());
+        dedup.push(event("5m", 3), 10_000);
+        assert_eq!(periods(&dedup.ready(11_000)), ["5m"]);
+        // 窗口过后大周期不抑制小周期
+        dedup.push(event("1h", 3), 400_000);
+        dedup.push(event("15m", 3), 400_100);
+        assert_eq!(periods(&dedup.flush()), ["1h"]);
+        dedup.push(event("15m", 3), 800_000);
+        assert_eq!(periods(&dedup.flush()), ["15m"]);
+    }
+
+    #[test]
+    fn no_hold_without_window() {
+        let mut dedup = Deduplicator::new(DedupConfig {
+            cross_interval_window: 0,
+            ..config()
+        });
+        dedup.push(event("5m", 3), 10_000);
+        dedup.push(event("1h", 3), 10_000);
+        assert_eq!(periods(&dedup.ready(10_000)), ["1h", "5m"]);
+    }
+
+    #[test]
+    fn cooldown() {
+        let mut dedup = Deduplicator::new(config());
+        dedup.push(event("5m", 3), 0);
+        assert_eq!(dedup.flush().len(), 1);
+        dedup.push(event("5m", 4), 300_000);
+        assert!(dedup.flush().is_empty());
+        dedup.push(event("5m", 3), 600_000);
+        let events = dedup.flush();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].suppressed, 1);
+    }
+
+    #[test]
+    fn escalation() {
+        let mut config = config();
+        config.rules.insert(
+            EventType::ConsecutiveMove,
+            DedupRule {
+                escalation_only: true,
+                min_escalation: 0.2,
+                ..Default::default()
+            },
+        );
+        let mut dedup = Deduplicator::new(config);
+        dedup.push(event("5m", 5), 0);
+        assert_eq!(dedup.flush().len(), 1);
+        // 增加不到 20% 被抑制
+        dedup.push(event("5m", 6), 300_000);
+        assert!(dedup.flush().is_empty());
+        dedup.push(event("5m", 7), 360_000);
+        assert_eq!(periods(&dedup.flush()), ["5m"]);
+        // 只有成交额增加不算升级
+        dedup.push(event_with_turnover("5m", 7, 1e9), 420_000);
+        assert!(dedup.flush().is_empty());
+        dedup.push(event_with_turnover("5m", 9, 1e7), 480_000);
+        assert_eq!(periods(&dedup.flush()), ["5m"]);
+    }
+
+    fn ranking(timestamp: u64) -> Event {
+        let payload = EventPayload::MarketRanking(MarketRanking {
+            symbol_count: 1,
+            gainers: HashMap::new(),
+            losers: HashMap::new(),
+            top_turnover: Vec::new(),
+            turnover_rank_changes: Vec::new(),
+        });
+        Event::new("".into(), "".into(), payload, timestamp, 0.0).unwrap()
+    }
+
+    #[test]
+    fn ranking_ignores_default_cooldown() {
+        let mut dedup = Deduplicator::new(config());
+        dedup.push(ranking(0), 0);
+        dedup.push(ranking(60_000), 60_000);
+        assert_eq!(dedup.flush().len(), 2);
+
+        // 显式配置的冷却时间仍然生效
+        let mut config = config();
+        config.rules.insert(
+            EventType::MarketRanking,
+            DedupRule {
+                cooldown: Some(300),
+                ..Default::default()
+            },
+        );
+        let mut dedup = Deduplicator::new(config);
+        dedup.push(ranking(0), 0);
+        dedup.push(ranking(60_000), 60_000);
+        assert_eq!(dedup.flush().len(), 1);
+        dedup.push(ranking(300_000), 300_000);
+        assert_eq!(dedup.flush().len(), 1);
+    }
+}
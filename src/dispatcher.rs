@@ -0,0 +1,157 @@
+use crate::config::ConfigReceiver;
+use crate::dedup::Deduplicator;
+use crate::helper::now_ms;
+use crate::server::hub::{Hub, Push};
+use crate::sink::FanOut;
+use crate::symbols::FilterReceiver;
+use crate::types::{Event, EventPayload};
+use serde_json::to_string_pretty;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+
+// 检测器通过该通道把事件交给分发器
+#[derive(Clone)]
+pub struct EventSender(mpsc::Sender<Event>);
+
+impl EventSender {
+    pub fn new(tx: mpsc::Sender<Event>) -> Self {
+        Self(tx)
+    }
+
+    /// 创建事件交给分发器，去重后写到配置的所有 sink
+    pub async fn emit(
+        &self,
+        symbol: String,
+        period: String,
+        payload: EventPayload,
+        timestamp: u64,
+        severity: f64,
+    ) {
+        let event = match Event::new(symbol, period, payload, timestamp, severity) {
+            Ok(event) => event,
+            Err(e) => {
+                error!("failed to create event: {:?}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.0.send(event).await {
+            error!("failed to dispatch event: {:?}", e);
+        }
+    }
+}
+
+// 事件输出
+pub enum Output {
+    // 写到配置的所有 sink，收盘k线由 worker 写入
+    Sinks(FanOut),
+    // 回测：写到 JSON Lines 文件，去重按事件时间而不是当前时间计算
+    File(BufWriter<File>),
+}
+
+// ========== 事件分发 ==========
+pub async fn dispatcher(
+    mut rx: mpsc::Receiver<Event>,
+    mut dedup: Deduplicator,
+    mut config: ConfigReceiver,
+    mut output: Output,
+    hub: Hub,
+    filter: FilterReceiver,
+) {
+    let mut latest: u64 = 0; // 回测时的当前时间
+    let mut ticker = tokio::time::interval(Duration::from_secs(60));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    // 定期取出去重等待期已过的事件
+    let mut release = tokio::time::interval(Duration::from_millis(250));
+    release.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(mut event) = event else {
+                    break;
+                };
+                latest = latest.max(event.timestamp);
+                let now = match output {
+                    Output::Sinks(_) => now_ms(),
+                    Output::File(_) => latest,
+                };
+                // 被排除的交易对或关闭的检测器直接丢弃
+                if !filter.borrow().enabled(&event.symbol, event.event_type()) {
+                    continue;
+                }
+                event.priority = config.borrow().severity.priority(event.severity);
+                dedup.push(event, now);
+                emit(dedup.ready(now), &config, &mut output, &hub);
+            }
+            _ = release.tick() => {
+                let now = match output {
+                    Output::Sinks(_) => now_ms(),
+                    Output::File(_) => latest,
+                };
+                emit(dedup.ready(now), &config, &mut output, &hub);
+            }
+            Ok(()) = config.changed() => {
+                let cfg = config.borrow_and_update().clone();
+                dedup.set_config(cfg.dedup.clone());
+                if let Output::Sinks(sinks) = &output {
+                    sinks.reload(&cfg);
+                }
+            }
+            _ = ticker.tick() => {
+                dedup.prune(match output {
+                    Output::Sinks(_) => now_ms(),
+                    Output::File(_) => latest,
+                });
+                let suppressed = dedup.take_suppressed();
+                if !suppressed.is_empty() {
+                    info!("suppressed events in the last minute: {:?}", suppressed);
+                }
+                // 下游不可用期间报告缓冲情况
+                if let Output::Sinks(sinks) = &output {
+                    for stats in sinks.metrics().iter().map(|m| m.stats()) {
+                        if stats.buffered > 0 {
+                            warn!("sink buffering: {:?}", stats);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // 发出还在等待去重的事件
+    emit(dedup.flush(), &config, &mut output, &hub);
+    if let Output::File(file) = &mut output {
+        if let Err(e) = file.flush() {
+            error!("failed to flush events: {:?}", e);
+        }
+    }
+}
+
+// 发出通过去重的事件
+fn emit(events: Vec<Event>, config: &ConfigReceiver, output: &mut Output, hub: &Hub) {
+    if events.is_empty() {
+        return;
+    }
+    let cfg = config.borrow().clone();
+    for event in events {
+        info!("New event: {}", to_string_pretty(&event).unwrap());
+        // 保存到最近事件并推送给 WebSocket 客户端
+        let shared = Arc::new(event);
+        hub.record(shared.clone());
+        if hub.has_event_subscribers() {
+            hub.publish(Push::Event(shared.clone()));
+        }
+        match output {
+            Output::Sinks(sinks) => sinks.send_event(&shared, &cfg),
+            Output::File(file) => {
+                if let Err(e) = writeln!(file, "{}", shared.to_json()) {
+                    error!("failed to write event: {:?}", e);
+                }
+            }
+        }
+    }
+}
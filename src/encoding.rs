@@ -0,0 +1,161 @@
+use crate::proto;
+use crate::types::Event;
+use anyhow::{anyhow, Result};
+use prost::Message;
+use serde::Deserialize;
+
+// sink 写出事件时使用的编码
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Msgpack,
+    Protobuf, // 格式见 proto/perpx.proto
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::Msgpack => "msgpack",
+            Encoding::Protobuf => "protobuf",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Encoding::Json, Encoding::Msgpack, Encoding::Protobuf]
+            .into_iter()
+            .find(|e| e.name() == name)
+    }
+
+    pub fn encode(&self, event: &Event) -> Result<Vec<u8>> {
+        Ok(match self {
+            Encoding::Json => event.to_json().into_bytes(),
+            // 使用带字段名的 map 格式，和 JSON 结构一致
+            Encoding::Msgpack => rmp_serde::to_vec_named(event)?,
+            Encoding::Protobuf => proto::Event::from(event).encode_to_vec(),
+        })
+    }
+
+    /// 解码 json / msgpack 消息，protobuf 消息只能解码为 proto::Event
+    pub fn decode(&self, message: &[u8]) -> Result<Event> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(message)?),
+            Encoding::Msgpack => Ok(rmp_serde::from_slice(message)?),
+            Encoding::Protobuf => Err(anyhow!("decode protobuf messages with proto::Event")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        ConsecutiveMove, EventPayload, Interval, MarketRanking, PremiumDeviation, Priority,
+        RankEntry,
+    };
+    use std::collections::HashMap;
+
+    fn events() -> Vec<Event> {
+        let entry = RankEntry {
+            symbol: "ETHUSDT".to_string(),
+            price: 2500.0,
+            value: 0.05,
+        };
+        let payloads = [
+            (
+                "5m",
+                EventPayload::ConsecutiveMove(ConsecutiveMove {
+                    count: 5,
+                    turnover: 1.5e9,
+                    direction: -1,
+                }),
+            ),
+            (
+                "",
+                EventPayload::PremiumDeviation(PremiumDeviation {
+                    reason: "mark_index".to_string(),
+                    mark_price: 100.5,
+                    index_price: 100.0,
+                    last_price: None,
+                    premium: 0.005,
+                    avg_premium: Some(0.001),
+                    basis: None,
+                }),
+            ),
+            (
+                "",
+                EventPayload::MarketRanking(MarketRanking {
+                    symbol_count: 300,
+                    gainers: HashMap::from([(Interval::Hour1, vec![entry.clone()])]),
+                    losers: HashMap::new(),
+                    top_turnover: vec![entry],
+                    turnover_rank_changes: Vec::new(),
+                }),
+            ),
+        ];
+        payloads
+            .into_iter()
+            .map(|(period, payload)| {
+                let mut event =
+                    Event::new("BTCUSDT".to_string(), period.to_string(), payload, 1, 2.5).unwrap();
+                event.priority = Priority::High;
+                event.suppressed = 3;
+                event
+            })
+            .collect()
+    }
+
+    fn value(event: &Event) -> serde_json::Value {
+        serde_json::to_value(event).unwrap()
+    }
+
+    #[test]
+    fn json_and_msgpack_round_trip() {
+        for encoding in [Encoding::Json, Encoding::Msgpack] {
+            for event in events() {
+                let decoded = encoding.decode(&encoding.encode(&event).unwrap()).unwrap();
+                assert_eq!(value(&decoded), value(&event), "{:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn protobuf_encoding() {
+        for event in events() {
+            let message = Encoding::Protobuf.encode(&event).unwrap();
+            assert!(Encoding::Protobuf.decode(&message).is_err());
+
+            let decoded = proto::Event::decode(message.as_slice()).unwrap();
+            assert_eq!(decoded, proto::Event::from(&event));
+            assert_eq!(decoded.id, event.id);
+            assert_eq!(decoded.symbol, event.symbol);
+            assert_eq!(decoded.period, event.period);
+            assert_eq!(value(&event)["event_type"], decoded.event_type.as_str());
+            assert_eq!(decoded.priority, proto::Priority::High as i32);
+            assert_eq!((decoded.severity, decoded.suppressed), (2.5, 3));
+            match (&event.payload, decoded.value.unwrap()) {
+                (EventPayload::ConsecutiveMove(_), proto::event::Value::ConsecutiveMove(v)) => {
+                    assert_eq!((v.count, v.direction), (5, -1));
+                }
+                (EventPayload::PremiumDeviation(_), proto::event::Value::PremiumDeviation(v)) => {
+                    assert_eq!((v.last_price, v.avg_premium), (None, Some(0.001)));
+                }
+                (EventPayload::MarketRanking(_), proto::event::Value::MarketRanking(v)) => {
+                    assert_eq!(v.gainers["1h"].entries.len(), 1);
+                    assert!(v.losers.is_empty());
+                }
+                _ => panic!("payload mismatch"),
+            }
+        }
+    }
+
+    #[test]
+    fn encoding_names() {
+        for encoding in [Encoding::Json, Encoding::Msgpack, Encoding::Protobuf] {
+            assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+        }
+        assert_eq!(Encoding::from_name("xml"), None);
+    }
+}
@@ -0,0 +1,682 @@
+use crate::config::{Config as AppConfig, FileCompression, FileFormat, FilesConfig};
+use crate::helper::now_ms;
+use crate::metrics::SinkMetrics;
+use crate::sink::EventSink;
+use crate::types::{ClosedBar, Event, Interval, Kline};
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate};
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+// ========== 本地文件 ==========
+// 事件和收盘k线按 UTC 日期（事件时间、k线开始时间）写到 <dir>/events/ 和 <dir>/klines/：
+//   jsonl：<date>.jsonl[.gz]，追加写入，重启后继续写同一个文件；
+//          gzip 每批写成一个完整的 member，进程被强制结束时已写出的批次仍然可读
+//   parquet：<date>-<创建时间>.parquet，关闭时才写入文件尾，写入中的文件以 .inprogress 结尾，
+//            每小时换一个新文件，进程被强制结束时最多丢失一小时的数据
+// 保留当天和前一天的文件打开，迟到的记录仍写到对应日期的文件
+// 文件读写都在 spawn_blocking 中进行，不占用 tokio 的工作线程
+
+const BATCH_SIZE: usize = 1000;
+// 收到记录后等待多久合并成一批写出，jsonl 每批写完 flush
+const BATCH_WINDOW: Duration = Duration::from_secs(1);
+const CHANNEL_CAPACITY: usize = 100000;
+
+enum Record {
+    Event(Arc<Event>),
+    Kline(ClosedBar),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Events,
+    Klines,
+}
+
+impl Kind {
+    fn dir_name(&self) -> &'static str {
+        match self {
+            Kind::Events => "events",
+            Kind::Klines => "klines",
+        }
+    }
+}
+
+// jsonl 中的一行k线
+#[derive(Serialize)]
+struct KlineRow<'a> {
+    symbol: &'a str,
+    interval: Interval,
+    #[serde(flatten)]
+    kline: &'a Kline,
+}
+
+#[derive(Clone)]
+pub struct FileSink {
+    tx: mpsc::Sender<Record>,
+    metrics: Arc<SinkMetrics>,
+}
+
+impl FileSink {
+    /// 创建输出目录并启动写入任务
+    pub fn spawn(cfg: &FilesConfig) -> Result<(Self, JoinHandle<()>)> {
+        let dir = PathBuf::from(&cfg.dir);
+        for kind in [Kind::Events, Kind::Klines] {
+            fs::create_dir_all(dir.join(kind.dir_name()))?;
+            #[cfg(feature = "parquet")]
+            parquet_file::warn_unfinished(&dir.join(kind.dir_name()));
+        }
+        let writer = Writer {
+            dir,
+            format: cfg.format,
+            compression: cfg.compression,
+            retention_days: cfg.retention_days,
+            open: BTreeMap::new(),
+        };
+        let metrics = Arc::new(SinkMetrics::new("files"));
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let task = tokio::spawn(write_loop(writer, rx, metrics.clone()));
+        Ok((Self { tx, metrics }, task))
+    }
+
+    // 写入任务跟不上时丢弃
+    fn send(&self, record: Record) {
+        if self.tx.try_send(record).is_err() {
+            SinkMetrics::incr(&self.metrics.dropped);
+        }
+    }
+}
+
+impl EventSink for FileSink {
+    fn send_event(&self, event: &Arc<Event>, cfg: &AppConfig) {
+        if event.priority >= cfg.sinks.files.min_priority {
+            self.send(Record::Event(event.clone()));
+        }
+    }
+
+    fn send_kline(&self, bar: &ClosedBar, cfg: &AppConfig) {
+        if cfg.sinks.files.klines {
+            self.send(Record::Kline(bar.clone()));
+        }
+    }
+
+    fn metrics(&self) -> Arc<SinkMetrics> {
+        self.metrics.clone()
+    }
+}
+
+// 毫秒时间戳对应的 UTC 日期
+fn utc_date(ts: u64) -> NaiveDate {
+    DateTime::from_timestamp_millis(ts as i64)
+        .unwrap_or_default()
+        .date_naive()
+}
+
+struct Writer {
+    dir: PathBuf,
+    format: FileFormat,
+    compression: FileCompression,
+    retention_days: u32,
+    open: BTreeMap<(Kind, NaiveDate), DayFile>,
+}
+
+impl Writer {
+    fn write(&mut self, batch: &[Record]) -> Result<()> {
+        // 按文件分组，每个文件一次写入
+        let mut groups: BTreeMap<(Kind, NaiveDate), Vec<&Record>> = BTreeMap::new();
+        for record in batch {
+            let key = match record {
+                Record::Event(event) => (Kind::Events, utc_date(event.timestamp)),
+                Record::Kline(bar) => (Kind::Klines, utc_date(bar.kline.start_ts)),
+            };
+            groups.entry(key).or_default().push(record);
+        }
+        let mut result = Ok(());
+        for (key, records) in groups {
+            if let Err(e) = self.write_group(key, &records) {
+                // 出错的文件关闭，下一批重新打开
+                if let Some(file) = self.open.remove(&key) {
+                    let _ = file.close();
+                }
+                result = Err(e);
+            }
+        }
+        self.rotate();
+        result
+    }
+
+    fn write_group(&mut self, key: (Kind, NaiveDate), records: &[&Record]) -> Result<()> {
+        if !self.open.contains_key(&key) {
+            let file = DayFile::open(&self.dir, key, self.format, self.compression)?;
+            self.open.insert(key, file);
+        }
+        self.open.get_mut(&key).unwrap().write(records)
+    }
+
+    // 关闭比最新日期早一天以上的文件和需要换新文件的 parquet 文件，有文件关闭时按保留天数清理
+    fn rotate(&mut self) {
+        let Some(latest) = self.open.keys().map(|(_, date)| *date).max() else {
+            return;
+        };
+        let expired: Vec<_> = self
+            .open
+            .iter()
+            .filter(|((_, date), file)| (latest - *date).num_days() > 1 || file.should_roll())
+            .map(|(key, _)| *key)
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+        for key in expired {
+            let file = self.open.remove(&key).unwrap();
+            if let Err(e) = file.close() {
+                error!(
+                    "failed to close {} file for {}: {:?}",
+                    key.0.dir_name(),
+                    key.1,
+                    e
+                );
+            }
+        }
+        self.cleanup();
+    }
+
+    fn close_all(&mut self) {
+        for ((kind, date), file) in std::mem::take(&mut self.open) {
+            if let Err(e) = file.close() {
+                error!(
+                    "failed to close {} file for {}: {:?}",
+                    kind.dir_name(),
+                    date,
+                    e
+                );
+            }
+        }
+    }
+
+    // 删除 retention_days 天前（按当前时间）的文件
+    fn cleanup(&self) {
+        if self.retention_days == 0 {
+            return;
+        }
+        let oldest = utc_date(now_ms()) - chrono::Days::new(self.retention_days as u64);
+        for kind in [Kind::Events, Kind::Klines] {
+            let dir = self.dir.join(kind.dir_name());
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let Some(date) = name
+                    .to_str()
+                    .and_then(|name| name.get(..10))
+                    .and_then(|prefix| NaiveDate::parse_from_str(prefix, "%Y-%m-%d").ok())
+                else {
+                    continue;
+                };
+                if date < oldest {
+                    match fs::remove_file(entry.path()) {
+                        Ok(()) => info!("removed expired file {}", entry.path().display()),
+                        Err(e) => warn!("failed to remove {}: {:?}", entry.path().display(), e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 某一天的输出文件
+enum DayFile {
+    Jsonl(BufWriter<File>),
+    JsonlGzip(BufWriter<File>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<parquet_file::ParquetFile>),
+}
+
+impl DayFile {
+    fn open(
+        dir: &Path,
+        (kind, date): (Kind, NaiveDate),
+        format: FileFormat,
+        compression: FileCompression,
+    ) -> Result<Self> {
+        let dir = dir.join(kind.dir_name());
+        match format {
+            FileFormat::Jsonl => {
+                let gzip = compression == FileCompression::Gzip;
+                let name = format!("{}.jsonl{}", date, if gzip { ".gz" } else { "" });
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(dir.join(name))?;
+                let file = BufWriter::new(file);
+                Ok(if gzip {
+                    DayFile::JsonlGzip(file)
+                } else {
+                    DayFile::Jsonl(file)
+                })
+            }
+            #[cfg(feature = "parquet")]
+            FileFormat::Parquet => Ok(DayFile::Parquet(Box::new(
+                parquet_file::ParquetFile::create(&dir, date, kind, compression)?,
+            ))),
+            #[cfg(not(feature = "parquet"))]
+            FileFormat::Parquet => anyhow::bail!("built without the parquet feature"),
+        }
+    }
+
+    fn write(&mut self, records: &[&Record]) -> Result<()> {
+        match self {
+            DayFile::Jsonl(file) => {
+                write_lines(&mut *file, records)?;
+                file.flush()?;
+            }
+            // 每批一个 gzip member，gzip/pandas/DuckDB 都能连续读出
+            DayFile::JsonlGzip(file) => {
+                let mut gzip = GzEncoder::new(&mut *file, flate2::Compression::default());
+                write_lines(&mut gzip, records)?;
+                gzip.finish()?.flush()?;
+            }
+            #[cfg(feature = "parquet")]
+            DayFile::Parquet(file) => return file.write(records),
+        }
+        Ok(())
+    }
+
+    fn should_roll(&self) -> bool {
+        match self {
+            DayFile::Jsonl(_) | DayFile::JsonlGzip(_) => false,
+            #[cfg(feature = "parquet")]
+            DayFile::Parquet(file) => file.should_roll(),
+        }
+    }
+
+    fn close(self) -> Result<()> {
+        match self {
+            DayFile::Jsonl(mut file) | DayFile::JsonlGzip(mut file) => file.flush()?,
+            #[cfg(feature = "parquet")]
+            DayFile::Parquet(file) => file.close()?,
+        }
+        Ok(())
+    }
+}
+
+// 每条记录一行 JSON
+fn write_lines<W: Write>(mut out: W, records: &[&Record]) -> Result<()> {
+    for record in records {
+        match record {
+            Record::Event(event) => writeln!(out, "{}", event.to_json())?,
+            Record::Kline(bar) => {
+                let row = KlineRow {
+                    symbol: &bar.symbol,
+                    interval: bar.interval,
+                    kline: &bar.kline,
+                };
+                writeln!(out, "{}", serde_json::to_string(&row)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// 在阻塞线程中使用 writer，完成后交还
+async fn blocking<T: Send + 'static>(
+    writer: Writer,
+    f: impl FnOnce(&mut Writer) -> T + Send + 'static,
+) -> (Writer, T) {
+    tokio::task::spawn_blocking(move || {
+        let mut writer = writer;
+        let result = f(&mut writer);
+        (writer, result)
+    })
+    .await
+    .expect("file writer panicked")
+}
+
+async fn write_loop(writer: Writer, mut rx: mpsc::Receiver<Record>, metrics: Arc<SinkMetrics>) {
+    let (mut writer, ()) = blocking(writer, |w| w.cleanup()).await;
+    let mut failing = false;
+    while let Some(first) = rx.recv().await {
+        let mut batch = vec![first];
+        let window = tokio::time::sleep(BATCH_WINDOW);
+        tokio::pin!(window);
+        while batch.len() < BATCH_SIZE {
+            tokio::select! {
+                _ = &mut window => break,
+                record = rx.recv() => match record {
+                    Some(record) => batch.push(record),
+                    None => break,
+                },
+            }
+        }
+        let count = batch.len() as u64;
+        let result;
+        (writer, result) = blocking(writer, move |w| w.write(&batch)).await;
+        match result {
+            Ok(()) => {
+                metrics.written.fetch_add(count, Ordering::Relaxed);
+                if failing {
+                    failing = false;
+                    info!("file sink recovered");
+                }
+            }
+            Err(e) => {
+                SinkMetrics::incr(&metrics.failures);
+                metrics.dropped.fetch_add(count, Ordering::Relaxed);
+                if !failing {
+                    failing = true;
+                    error!("failed to write files, dropping records: {:?}", e);
+                }
+            }
+        }
+    }
+    blocking(writer, |w| w.close_all()).await;
+}
+
+// ========== parquet ==========
+#[cfg(feature = "parquet")]
+mod parquet_file {
+    use super::{Kind, Record};
+    use crate::config::FileCompression;
+    use crate::helper::now_ms;
+    use anyhow::Result;
+    use arrow_array::{
+        ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray, UInt32Array,
+        UInt64Array,
+    };
+    use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+    use chrono::NaiveDate;
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+    use parquet::file::properties::WriterProperties;
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tracing::warn;
+
+    // 每个 row group 的最大行数
+    const ROW_GROUP_SIZE: usize = 100000;
+    const IN_PROGRESS: &str = ".inprogress";
+    // 文件打开多久后换一个新文件
+    const ROLL_INTERVAL: Duration = Duration::from_secs(3600);
+
+    fn timestamp() -> DataType {
+        DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+    }
+
+    fn events_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new("timestamp", timestamp(), false),
+            Field::new("symbol", DataType::Utf8, false),
+            Field::new("period", DataType::Utf8, false),
+            Field::new("event_type", DataType::Utf8, false),
+            Field::new("priority", DataType::Utf8, false),
+            Field::new("severity", DataType::Float64, false),
+            Field::new("suppressed", DataType::UInt64, false),
+            Field::new("schema_version", DataType::UInt32, false),
+            Field::new("value", DataType::Utf8, false), // 指标结果的 JSON
+        ]))
+    }
+
+    fn klines_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("symbol", DataType::Utf8, false),
+            Field::new("interval", DataType::Utf8, false),
+            Field::new("start_ts", timestamp(), false),
+            Field::new("open", DataType::Float64, false),
+            Field::new("high", DataType::Float64, false),
+            Field::new("low", DataType::Float64, false),
+            Field::new("close", DataType::Float64, false),
+            Field::new("volume", DataType::Float64, false),
+        ]))
+    }
+
+    pub struct ParquetFile {
+        kind: Kind,
+        path: PathBuf, // 关闭后的文件名
+        writer: ArrowWriter<File>,
+        opened: Instant,
+    }
+
+    impl ParquetFile {
+        pub fn create(
+            dir: &Path,
+            date: NaiveDate,
+            kind: Kind,
+            compression: FileCompression,
+        ) -> Result<Self> {
+            let path = dir.join(format!("{}-{}.parquet", date, now_ms()));
+            let compression = match compression {
+                FileCompression::None => Compression::UNCOMPRESSED,
+                FileCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+                FileCompression::Snappy => Compression::SNAPPY,
+                FileCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+            };
+            let props = WriterProperties::builder()
+                .set_compression(compression)
+                .set_max_row_group_size(ROW_GROUP_SIZE)
+                .build();
+            let schema = match kind {
+                Kind::Events => events_schema(),
+                Kind::Klines => klines_schema(),
+            };
+            let file = File::create(in_progress(&path))?;
+            let writer = ArrowWriter::try_new(file, schema, Some(props))?;
+            Ok(Self {
+                kind,
+                path,
+                writer,
+                opened: Instant::now(),
+            })
+        }
+
+        pub fn write(&mut self, records: &[&Record]) -> Result<()> {
+            let batch = match self.kind {
+                Kind::Events => events_batch(records)?,
+                Kind::Klines => klines_batch(records)?,
+            };
+            self.writer.write(&batch)?;
+            Ok(())
+        }
+
+        pub fn should_roll(&self) -> bool {
+            self.opened.elapsed() >= ROLL_INTERVAL
+        }
+
+        // 写入文件尾后去掉 .inprogress 后缀
+        pub fn close(self) -> Result<()> {
+            self.writer.close()?;
+            fs::rename(in_progress(&self.path), &self.path)?;
+            Ok(())
+        }
+    }
+
+    fn events_batch(records: &[&Record]) -> Result<RecordBatch> {
+        let events: Vec<_> = records
+            .iter()
+            .filter_map(|r| match r {
+                Record::Event(event) => Some(event.as_ref()),
+                Record::Kline(_) => None,
+            })
+            .collect();
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|e| e.id.as_str()),
+            )),
+            Arc::new(
+                TimestampMillisecondArray::from_iter_values(
+                    events.iter().map(|e| e.timestamp as i64),
+                )
+                .with_timezone("UTC"),
+            ),
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|e| e.symbol.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|e| e.period.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|e| format!("{:?}", e.event_type())),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                events
+                    .iter()
+                    .map(|e| format!("{:?}", e.priority).to_lowercase()),
+            )),
+            Arc::new(Float64Array::from_iter_values(
+                events.iter().map(|e| e.severity),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                events.iter().map(|e| e.suppressed),
+            )),
+            Arc::new(UInt32Array::from_iter_values(
+                events.iter().map(|e| e.schema_version),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|e| e.value_json()),
+            )),
+        ];
+        Ok(RecordBatch::try_new(events_schema(), columns)?)
+    }
+
+    fn klines_batch(records: &[&Record]) -> Result<RecordBatch> {
+        let bars: Vec<_> = records
+            .iter()
+            .filter_map(|r| match r {
+                Record::Kline(bar) => Some(bar),
+                Record::Event(_) => None,
+            })
+            .collect();
+        let prices = |f: fn(&crate::types::Kline) -> f64| -> ArrayRef {
+            Arc::new(Float64Array::from_iter_values(
+                bars.iter().map(|b| f(&b.kline)),
+            ))
+        };
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                bars.iter().map(|b| b.symbol.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                bars.iter().map(|b| b.interval.to_string()),
+            )),
+            Arc::new(
+                TimestampMillisecondArray::from_iter_values(
+                    bars.iter().map(|b| b.kline.start_ts as i64),
+                )
+                .with_timezone("UTC"),
+            ),
+            prices(|k| k.open),
+            prices(|k| k.high),
+            prices(|k| k.low),
+            prices(|k| k.close),
+            prices(|k| k.volume),
+        ];
+        Ok(RecordBatch::try_new(klines_schema(), columns)?)
+    }
+
+    /// 上次运行没有正常关闭的文件没有文件尾，无法读取
+    pub fn warn_unfinished(dir: &Path) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().ends_with(IN_PROGRESS) {
+                warn!(
+                    "unfinished parquet file from a previous run: {}",
+                    entry.path().display()
+                );
+            }
+        }
+    }
+
+    fn in_progress(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(IN_PROGRESS);
+        PathBuf::from(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn read_gzip(path: &Path) -> Vec<String> {
+        let mut text = String::new();
+        MultiGzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    // 每批写完后 gzip 文件即完整可读，不依赖关闭时写入文件尾
+    #[test]
+    fn gzip_readable_after_each_batch() {
+        let dir = std::env::temp_dir().join(format!("perpx-gzip-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("events")).unwrap();
+        let mut writer = Writer {
+            dir: dir.clone(),
+            format: FileFormat::Jsonl,
+            compression: FileCompression::Gzip,
+            retention_days: 0,
+            open: BTreeMap::new(),
+        };
+        let path = dir.join("events").join("2024-01-01.jsonl.gz");
+        for batch in 1..=2 {
+            let records: Vec<Record> = (0..3)
+                .map(|i| Record::Event(Arc::new(Event::fixture("BTCUSDT", 1_704_067_200_000 + i))))
+                .collect();
+            writer.write(&records).unwrap();
+            // 文件仍然打开，没有关闭
+            assert_eq!(read_gzip(&path).len(), batch * 3);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 发送端关闭后写入任务关闭所有文件，重新打开后继续追加
+    #[tokio::test]
+    async fn gzip_closed_on_shutdown() {
+        let dir = std::env::temp_dir().join(format!("perpx-files-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cfg = FilesConfig {
+            dir: dir.to_string_lossy().into_owned(),
+            format: FileFormat::Jsonl,
+            compression: FileCompression::Gzip,
+            retention_days: 0,
+        };
+        // 2024-01-01 和 2024-01-02
+        let days = [1_704_067_200_000, 1_704_153_600_000];
+        for round in 0..2 {
+            let (sink, task) = FileSink::spawn(&cfg).unwrap();
+            for ts in days {
+                sink.send(Record::Event(Arc::new(Event::fixture(
+                    "BTCUSDT",
+                    ts + round,
+                ))));
+            }
+            drop(sink);
+            task.await.unwrap();
+        }
+        for (date, ts) in ["2024-01-01", "2024-01-02"].into_iter().zip(days) {
+            let lines = read_gzip(&dir.join("events").join(format!("{}.jsonl.gz", date)));
+            assert_eq!(lines.len(), 2);
+            let event: Event = serde_json::from_str(&lines[1]).unwrap();
+            assert_eq!(event.timestamp, ts + 1);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+use crate::config::FundingRateConfig;
+use crate::dispatcher::EventSender;
+use crate::helper::{annualize_funding_rate, DEFAULT_FUNDING_INTERVAL};
+use crate::types::{
+    EventPayload, FundingCountdown, FundingIntervalChange, FundingRate, FundingSchedule,
+};
+use tracing::debug;
+
+// 根据 markPrice 中的下次结算时间更新结算周期，周期变化时返回 (旧周期, 新周期)
+// next_funding_time 后移说明刚完成一次结算，差值即为结算周期
+pub fn update_funding_schedule(
+    schedule: &mut FundingSchedule,
+    next_funding_time: u64,
+) -> Option<(u64, u64)> {
+    if next_funding_time > schedule.next_funding_time {
+        let new_interval = next_funding_time - schedule.next_funding_time;
+        let old_interval = schedule.interval.replace(new_interval);
+        schedule.next_funding_time = next_funding_time;
+        schedule.alerted.clear();
+        return old_interval
+            .filter(|&old| old != new_interval)
+            .map(|old| (old, new_interval));
+    }
+    if next_funding_time < schedule.next_funding_time {
+        // 结算时间提前（交易所缩短了结算周期），等下一次结算再确认新周期
+        schedule.next_funding_time = next_funding_time;
+        schedule.alerted.clear();
+    }
+    None
+}
+
+// 使用 fundingInfo 中的结算周期，周期变化时返回 (旧周期, 新周期)
+// 没有列出的交易对只在还不知道结算周期时使用默认值，避免覆盖观察到的周期
+pub fn apply_funding_info(
+    schedule: &mut FundingSchedule,
+    interval: Option<u64>,
+) -> Option<(u64, u64)> {
+    let Some(new_interval) = interval else {
+        schedule.interval.get_or_insert(DEFAULT_FUNDING_INTERVAL);
+        return None;
+    };
+    schedule
+        .interval
+        .replace(new_interval)
+        .filter(|&old| old != new_interval)
+        .map(|old| (old, new_interval))
+}
+
+// 结算倒计时提醒：同一结算周期内每个提醒点只发一次，同时命中多个时只发最近的一个
+pub fn due_countdown(
+    schedule: &mut FundingSchedule,
+    event_time: u64,
+    funding_rate: f64,
+    config: &FundingRateConfig,
+) -> Option<u64> {
+    let remaining = schedule.next_funding_time.saturating_sub(event_time);
+    if remaining == 0 || funding_rate.abs() < config.countdown_min_rate {
+        return None;
+    }
+    let due = config
+        .countdown_minutes
+        .iter()
+        .copied()
+        .filter(|&minutes| remaining <= minutes * 60 * 1000 && !schedule.alerted.contains(&minutes))
+        .collect::<Vec<u64>>();
+    let minutes = due.iter().min().copied()?;
+    schedule.alerted.extend(due);
+    Some(minutes)
+}
+
+// 资金费率
+pub async fn process_funding_rate(
+    symbol: String,
+    event_time: u64,
+    funding_rate: String,
+    next_funding_time: u64,
+    funding_interval: u64,
+    events: EventSender,
+) {
+    debug!("process_funding_rate {:?} {}", symbol, funding_rate);
+    let rate: f64 = funding_rate.parse().unwrap_or(0.0);
+    let payload = EventPayload::FundingRate(FundingRate {
+        funding_rate: rate,
+        next_funding_time,
+        funding_interval_hours: funding_interval as f64 / 3_600_000.0,
+        annualized_rate: annualize_funding_rate(rate, funding_interval),
+    });
+    let severity = funding_severity(rate, funding_interval);
+    events
+        .emit(symbol, "".to_string(), payload, event_time, severity)
+        .await;
+}
+
+// 资金费率结算倒计时
+pub async fn process_funding_countdown(
+    symbol: String,
+    event_time: u64,
+    funding_rate: String,
+    next_funding_time: u64,
+    funding_interval: u64,
+    minutes: u64,
+    events: EventSender,
+) {
+    debug!(
+        "process_funding_countdown {:?} {} {}min",
+        symbol, funding_rate, minutes
+    );
+    let rate: f64 = funding_rate.parse().unwrap_or(0.0);
+    let payload = EventPayload::FundingCountdown(FundingCountdown {
+        funding_rate: rate,
+        next_funding_time,
+        minutes_before: minutes,
+        funding_interval_hours: funding_interval as f64 / 3_600_000.0,
+        annualized_rate: annualize_funding_rate(rate, funding_interval),
+    });
+    let severity = funding_severity(rate, funding_interval);
+    events
+        .emit(symbol, "".to_string(), payload, event_time, severity)
+        .await;
+}
+
+// 资金费率结算周期变化，例如 8h 调整为 4h 或 1h
+pub async fn process_funding_interval_change(
+    symbol: String,
+    event_time: u64,
+    old_interval: u64,
+    new_interval: u64,
+    next_funding_time: u64,
+    events: EventSender,
+) {
+    debug!(
+        "process_funding_interval_change {:?} {} -> {}",
+        symbol, old_interval, new_interval
+    );
+    let payload = EventPayload::FundingIntervalChange(FundingIntervalChange {
+        old_interval_hours: old_interval as f64 / 3_600_000.0,
+        new_interval_hours: new_interval as f64 / 3_600_000.0,
+        next_funding_time,
+    });
+    // 结算周期调整本身就值得关注
+    events
+        .emit(symbol, "".to_string(), payload, event_time, 1.0)
+        .await;
+}
+
+// 严重程度：年化费率绝对值，100% 年化为 1
+fn funding_severity(rate: f64, funding_interval: u64) -> f64 {
+    annualize_funding_rate(rate, funding_interval).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3600 * 1000;
+    const MIN: u64 = 60 * 1000;
+
+    fn config() -> FundingRateConfig {
+        FundingRateConfig {
+            countdown_minutes: vec![30, 10, 5],
+            countdown_min_rate: 0.0005,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn countdown_minutes_before() {
+        let mut schedule = FundingSchedule::new(8 * HOUR, Some(8 * HOUR));
+        let config = config();
+        // 费率不够高或还没到提醒点
+        assert_eq!(
+            due_countdown(&mut schedule, 7 * HOUR + 40 * MIN, 0.0001, &config),
+            None
+        );
+        assert_eq!(
+            due_countdown(&mut schedule, 7 * HOUR, -0.001, &config),
+            None
+        );
+        // 负费率按绝对值判断，每个提醒点只发一次
+        assert_eq!(
+            due_countdown(&mut schedule, 7 * HOUR + 30 * MIN, -0.001, &config),
+            Some(30)
+        );
+        assert_eq!(
+            due_countdown(&mut schedule, 7 * HOUR + 40 * MIN, -0.001, &config),
+            None
+        );
+        // 错过 10 分钟的提醒点时只发最近的 5 分钟，之后不再补发
+        assert_eq!(
+            due_countdown(&mut schedule, 7 * HOUR + 56 * MIN, 0.001, &config),
+            Some(5)
+        );
+        assert_eq!(
+            due_countdown(&mut schedule, 7 * HOUR + 57 * MIN, 0.001, &config),
+            None
+        );
+        // 已经结算
+        assert_eq!(due_countdown(&mut schedule, 8 * HOUR, 0.001, &config), None);
+
+        // 进入下一个结算周期后重新提醒
+        assert_eq!(update_funding_schedule(&mut schedule, 16 * HOUR), None);
+        assert_eq!(
+            due_countdown(&mut schedule, 15 * HOUR + 50 * MIN, 0.001, &config),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn interval_change_from_settlement() {
+        // 启动时还不知道结算周期，第一次结算只确认周期
+        let mut schedule = FundingSchedule::new(8 * HOUR, None);
+        assert_eq!(update_funding_schedule(&mut schedule, 8 * HOUR), None);
+        assert_eq!(update_funding_schedule(&mut schedule, 16 * HOUR), None);
+        assert_eq!(schedule.interval, Some(8 * HOUR));
+
+        // 交易所把 8h 调整为 4h：结算时间提前，下一次结算后确认新周期
+        assert_eq!(update_funding_schedule(&mut schedule, 12 * HOUR), None);
+        assert_eq!(schedule.next_funding_time, 12 * HOUR);
+        assert_eq!(
+            update_funding_schedule(&mut schedule, 16 * HOUR),
+            Some((8 * HOUR, 4 * HOUR))
+        );
+        assert_eq!(update_funding_schedule(&mut schedule, 20 * HOUR), None);
+        assert_eq!(schedule.interval, Some(4 * HOUR));
+    }
+
+    #[test]
+    fn interval_change_from_funding_info() {
+        let mut schedule = FundingSchedule::new(8 * HOUR, None);
+        // 没有列出的交易对使用默认周期，但不覆盖已知的周期
+        assert_eq!(apply_funding_info(&mut schedule, None), None);
+        assert_eq!(schedule.interval, Some(DEFAULT_FUNDING_INTERVAL));
+        assert_eq!(
+            apply_funding_info(&mut schedule, Some(HOUR)),
+            Some((8 * HOUR, HOUR))
+        );
+        assert_eq!(apply_funding_info(&mut schedule, Some(HOUR)), None);
+        assert_eq!(apply_funding_info(&mut schedule, None), None);
+        assert_eq!(schedule.interval, Some(HOUR));
+    }
+}
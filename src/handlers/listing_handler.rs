@@ -0,0 +1,25 @@
+use crate::dispatcher::EventSender;
+use crate::types::{EventPayload, EventType, ListingChange};
+use tracing::debug;
+
+// 新上线/下架
+pub async fn process_listing_change(
+    symbol: String,
+    event_type: EventType,
+    timestamp: u64,
+    change: ListingChange,
+    events: EventSender,
+) {
+    debug!(
+        "process_listing_change {:?} {:?} {}",
+        symbol, event_type, change.reason
+    );
+    let payload = match event_type {
+        EventType::Delisting => EventPayload::Delisting(change),
+        _ => EventPayload::NewListing(change),
+    };
+    // 上线/下架事件默认为高优先级
+    events
+        .emit(symbol, "".to_string(), payload, timestamp, 2.0)
+        .await;
+}
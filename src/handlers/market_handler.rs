@@ -0,0 +1,65 @@
+use crate::dispatcher::EventSender;
+use crate::types::{BtcDivergence, EventPayload, Interval, MarketBreadth, MarketRanking};
+use tracing::debug;
+
+// 全市场排行
+pub async fn process_market_ranking(timestamp: u64, ranking: MarketRanking, events: EventSender) {
+    debug!("process_market_ranking {} symbols", ranking.symbol_count);
+    // 定期快照，仅供参考
+    events
+        .emit(
+            "".to_string(),
+            "".to_string(),
+            EventPayload::MarketRanking(ranking),
+            timestamp,
+            0.0,
+        )
+        .await;
+}
+
+// 市场宽度进入极端（普涨/普跌）
+pub async fn process_market_breadth(
+    interval: Interval,
+    timestamp: u64,
+    breadth: MarketBreadth,
+    severity: f64,
+    events: EventSender,
+) {
+    debug!(
+        "process_market_breadth {} {:?} up {:.2} down {:.2}",
+        interval, breadth.state, breadth.up_ratio, breadth.down_ratio
+    );
+    events
+        .emit(
+            "".to_string(),
+            interval.to_string(),
+            EventPayload::MarketBreadth(breadth),
+            timestamp,
+            severity,
+        )
+        .await;
+}
+
+// 相对 BTC 强弱背离
+pub async fn process_btc_divergence(
+    symbol: String,
+    interval: Interval,
+    timestamp: u64,
+    divergence: BtcDivergence,
+    severity: f64,
+    events: EventSender,
+) {
+    debug!(
+        "process_btc_divergence {:?} {} z {:.2}",
+        symbol, interval, divergence.z_score
+    );
+    events
+        .emit(
+            symbol,
+            interval.to_string(),
+            EventPayload::BtcDivergence(divergence),
+            timestamp,
+            severity,
+        )
+        .await;
+}
@@ -1 +1,5 @@
-pub mod trend_handler;
\ No newline at end of file
+pub mod funding_handler;
+pub mod listing_handler;
+pub mod market_handler;
+pub mod premium_handler;
+pub mod trend_handler;
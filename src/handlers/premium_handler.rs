@@ -0,0 +1,169 @@
+use crate::config::PremiumConfig;
+use crate::dispatcher::EventSender;
+use crate::types::{EventPayload, PremiumDeviation, PremiumSample, PremiumState};
+use tracing::debug;
+
+// 加入新的溢价样本，偏离超过阈值且距上次事件超过 premium_interval 时返回 (原因, 样本)
+pub fn detect_premium_deviation(
+    state: &mut PremiumState,
+    event_time: u64,
+    mark_price: f64,
+    index_price: f64,
+    last_price: Option<f64>,
+    config: &PremiumConfig,
+) -> Option<(&'static str, PremiumSample)> {
+    if mark_price <= 0.0 || index_price <= 0.0 {
+        return None;
+    }
+    let premium = (mark_price - index_price) / index_price;
+    let avg_premium = state.push(premium, config.window);
+
+    let reason = if premium.abs() > config.mark_index_threshold {
+        "mark_index"
+    } else if last_price
+        .is_some_and(|p| ((p - mark_price) / mark_price).abs() > config.last_mark_threshold)
+    {
+        "last_mark"
+    } else if state.samples.len() >= config.window
+        && avg_premium.is_some_and(|avg| (premium - avg).abs() > config.avg_deviation)
+    {
+        // 样本数达到窗口大小后才比较滚动均值
+        "rolling_avg"
+    } else {
+        return None;
+    };
+    if event_time.saturating_sub(state.time) < config.premium_interval * 1000 {
+        return None;
+    }
+    state.time = event_time;
+
+    // 严重程度：各项偏离相对阈值的最大倍数
+    let severity = [
+        premium.abs() / config.mark_index_threshold,
+        last_price.map_or(0.0, |p| {
+            ((p - mark_price) / mark_price).abs() / config.last_mark_threshold
+        }),
+        avg_premium.map_or(0.0, |avg| (premium - avg).abs() / config.avg_deviation),
+    ]
+    .into_iter()
+    .fold(0.0, f64::max);
+    let sample = PremiumSample {
+        mark_price,
+        index_price,
+        last_price,
+        premium,
+        avg_premium,
+        severity,
+    };
+    Some((reason, sample))
+}
+
+// 溢价/基差偏离
+pub async fn process_premium_deviation(
+    symbol: String,
+    event_time: u64,
+    reason: &'static str,
+    sample: PremiumSample,
+    events: EventSender,
+) {
+    debug!(
+        "process_premium_deviation {:?} {} {:.6}",
+        symbol, reason, sample.premium
+    );
+    // 最新价相对标记价格的偏离
+    let basis = sample
+        .last_price
+        .map(|p| (p - sample.mark_price) / sample.mark_price);
+    let payload = EventPayload::PremiumDeviation(PremiumDeviation {
+        reason: reason.to_string(),
+        mark_price: sample.mark_price,
+        index_price: sample.index_price,
+        last_price: sample.last_price,
+        premium: sample.premium,
+        avg_premium: sample.avg_premium,
+        basis,
+    });
+    events
+        .emit(symbol, "".to_string(), payload, event_time, sample.severity)
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PremiumConfig {
+        PremiumConfig {
+            window: 3,
+            premium_interval: 60,
+            ..Default::default()
+        }
+    }
+
+    // 指数价格固定为 100，第 n 秒的标记价格
+    fn detect(state: &mut PremiumState, secs: u64, mark_price: f64) -> Option<&'static str> {
+        detect_premium_deviation(state, secs * 1000, mark_price, 100.0, None, &config())
+            .map(|(reason, _)| reason)
+    }
+
+    #[test]
+    fn mark_index_threshold() {
+        let mut state = PremiumState::default();
+        assert_eq!(detect(&mut state, 1000, 100.5), None);
+        let (reason, sample) =
+            detect_premium_deviation(&mut state, 1_001_000, 101.0, 100.0, Some(101.0), &config())
+                .unwrap();
+        assert_eq!(reason, "mark_index");
+        assert!((sample.premium - 0.01).abs() < 1e-9);
+        // 严重程度取最大的倍数：偏离滚动均值 0.005 / 0.002
+        assert!((sample.severity - 2.5).abs() < 1e-9);
+        assert_eq!(sample.last_price, Some(101.0));
+        // 价格无效时不计入样本
+        assert_eq!(detect(&mut state, 1002, 0.0), None);
+        assert_eq!(state.samples.len(), 2);
+    }
+
+    #[test]
+    fn last_mark_threshold() {
+        let mut state = PremiumState::default();
+        let detect = |state: &mut PremiumState, last_price| {
+            detect_premium_deviation(state, 1_000_000, 100.0, 100.0, Some(last_price), &config())
+                .map(|(reason, _)| reason)
+        };
+        assert_eq!(detect(&mut state, 100.2), None);
+        assert_eq!(detect(&mut state, 99.6), Some("last_mark"));
+    }
+
+    #[test]
+    fn rolling_avg_needs_full_window() {
+        let mut state = PremiumState::default();
+        assert_eq!(detect(&mut state, 1000, 100.0), None);
+        assert_eq!(detect(&mut state, 1001, 100.0), None);
+        // 样本数达到窗口大小前不比较滚动均值
+        let mut partial = state.clone();
+        partial.samples.pop_front();
+        assert_eq!(detect(&mut partial, 1002, 100.3), None);
+        assert_eq!(detect(&mut state, 1002, 100.3), Some("rolling_avg"));
+    }
+
+    #[test]
+    fn cross_back_and_interval() {
+        // 只看 mark/index 溢价
+        let config = PremiumConfig {
+            avg_deviation: 1.0,
+            ..config()
+        };
+        let mut state = PremiumState::default();
+        let mut detect = |secs: u64, mark_price| {
+            detect_premium_deviation(&mut state, secs * 1000, mark_price, 100.0, None, &config)
+                .map(|(reason, _)| reason)
+        };
+        assert_eq!(detect(1000, 101.0), Some("mark_index"));
+        // premium_interval 内不重复发送
+        assert_eq!(detect(1030, 101.0), None);
+        // 超过间隔后回到阈值内不发送，再次越过（负溢价）时发送
+        assert_eq!(detect(1100, 100.2), None);
+        assert_eq!(detect(1110, 99.0), Some("mark_index"));
+        assert_eq!(detect(1120, 99.0), None);
+    }
+}
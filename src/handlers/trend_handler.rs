@@ -1,8 +1,12 @@
-use crate::redis::RedisQueue;
-use crate::types::{Event, EventType, Interval, Kline};
-use serde_json::{json, to_string_pretty};
-use std::sync::Arc;
-use tracing::{debug, error, info};
+use crate::dispatcher::EventSender;
+use crate::helper::turnover_weight;
+use crate::types::{ConsecutiveMove, EventPayload, Interval, Kline, VolatilitySpike};
+use tracing::debug;
+
+// 连续涨跌最多回看的k线数量，max_kline_count 不能小于该值
+pub const MAX_CONSECUTIVE_LOOKBACK: usize = 10;
+// 低于该振幅视为没有波动
+const MIN_AMPLITUDE: f64 = 0.0001;
 
 // 异常波动
 pub async fn process_volatility_spike(
@@ -10,7 +14,7 @@ pub async fn process_volatility_spike(
     interval: Interval,
     klines: Vec<Kline>,
     turnover: String,
-    queue: Arc<RedisQueue>,
+    events: EventSender,
 ) {
     debug!(
         "process_volatility_spike {:?} {:?} {}",
@@ -39,33 +43,29 @@ pub async fn process_volatility_spike(
         -1
     };
 
-    if current_amp > 0.0001 && current_amp > avg_prev_amp * 2.0 {
+    if current_amp > MIN_AMPLITUDE && current_amp > avg_prev_amp * 2.0 {
+        // 严重程度：振幅倍数相对阈值(2倍)的比例，按成交额加权
+        // 之前的k线没有波动时按最小振幅计算，避免除以0
+        let turnover: f64 = turnover.parse().unwrap_or(0.0);
+        let severity =
+            current_amp / avg_prev_amp.max(MIN_AMPLITUDE) / 2.0 * turnover_weight(turnover);
         // 发出事件
-        let value = json!({
-            "amplitude": current_amp,
-            "avg_amplitude": avg_prev_amp,
-            "volume": current.volume,
-            "turnover": turnover,
-            "direction": direction
-        })
-        .as_object()
-        .unwrap()
-        .clone();
-        let new_event = Event {
-            symbol: symbol.to_string(),
-            event_type: EventType::VolatilitySpike,
-            period: interval.to_string(),
-            value,
-            timestamp: current.start_ts,
-        };
-        info!("New event: {}", to_string_pretty(&new_event).unwrap());
-        // 写到redis
-        if let Err(e) = queue
-            .push("events", new_event.to_json().as_str(), None)
-            .await
-        {
-            error!("failed to push event to redis: {:?}", e);
-        }
+        let payload = EventPayload::VolatilitySpike(VolatilitySpike {
+            amplitude: current_amp,
+            avg_amplitude: avg_prev_amp,
+            volume: current.volume,
+            turnover,
+            direction,
+        });
+        events
+            .emit(
+                symbol,
+                interval.to_string(),
+                payload,
+                current.start_ts,
+                severity,
+            )
+            .await;
     } else {
         // 只输出日志
         debug!(
@@ -83,7 +83,7 @@ pub async fn process_consecutive_move(
     interval: Interval,
     klines: Vec<Kline>,
     turnover: String,
-    queue: Arc<RedisQueue>,
+    events: EventSender,
 ) {
     debug!(
         "process_consecutive_move {:?} {:?} {}",
@@ -100,7 +100,7 @@ pub async fn process_consecutive_move(
     // 最大取10个周期
     let len = klines.len();
     // 取的长度：不超过 10，不少于 3
-    let take_len = len.min(10).max(3);
+    let take_len = len.clamp(3, MAX_CONSECUTIVE_LOOKBACK);
     // 从后往前取
     let slice = &klines[len - take_len..];
 
@@ -130,29 +130,23 @@ pub async fn process_consecutive_move(
 
     if count >= 3 {
         // 至少连续3个周期才发出事件
-        let value = json!({
-            "count": count.clone(),
-            "turnover": turnover,
-            "direction": trend
-        })
-        .as_object()
-        .unwrap()
-        .clone();
-        let new_event = Event {
-            event_type: EventType::ConsecutiveMove,
-            symbol: symbol.to_string(),
-            period: interval.to_string(),
-            value,
-            timestamp: klines.last().unwrap().start_ts,
-        };
-        info!("New event: {}", to_string_pretty(&new_event).unwrap());
-        // 写到redis
-        if let Err(e) = queue
-            .push("events", new_event.to_json().as_str(), None)
-            .await
-        {
-            error!("failed to push event to redis: {:?}", e);
-        }
+        // 严重程度：连续周期数相对阈值(3个)的比例，按成交额加权
+        let turnover: f64 = turnover.parse().unwrap_or(0.0);
+        let severity = count as f64 / 3.0 * turnover_weight(turnover);
+        let payload = EventPayload::ConsecutiveMove(ConsecutiveMove {
+            count,
+            turnover,
+            direction: trend,
+        });
+        events
+            .emit(
+                symbol,
+                interval.to_string(),
+                payload,
+                klines.last().unwrap().start_ts,
+                severity,
+            )
+            .await;
     } else {
         // 只输出日志
         debug!(
@@ -165,34 +159,31 @@ pub async fn process_consecutive_move(
     }
 }
 
-pub async fn process_funding_rate(
-    symbol: String,
-    event_time: u64,
-    funding_rate: String,
-    next_funding_time: u64,
-    queue: Arc<RedisQueue>,
-) {
-    debug!("process_funding_rate {:?} {}", symbol, funding_rate);
-    let value = json!({
-        "funding_rate": funding_rate.clone(),
-        "next_funding_time": next_funding_time.clone()
-    })
-    .as_object()
-    .unwrap()
-    .clone();
-    let new_event = Event {
-        event_type: EventType::FundingRate,
-        symbol: symbol.to_string(),
-        period: "".to_string(),
-        value,
-        timestamp: event_time,
-    };
-    info!("New event: {}", to_string_pretty(&new_event).unwrap());
-    // 写到redis
-    if let Err(e) = queue
-        .push("events", new_event.to_json().as_str(), None)
-        .await
-    {
-        error!("failed to push event to redis: {:?}", e);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn volatility_spike_after_flat_bars() {
+        let mut klines: Vec<Kline> = (0..3)
+            .map(|i| Kline::new(i * 300_000, 100.0, 1.0))
+            .collect();
+        let mut current = Kline::new(900_000, 100.0, 1.0);
+        current.update(101.0, 1.0);
+        klines.push(current);
+        let (tx, mut rx) = mpsc::channel(1);
+        process_volatility_spike(
+            "BTCUSDT".into(),
+            Interval::Min5,
+            klines,
+            "1e8".into(),
+            EventSender::new(tx),
+        )
+        .await;
+        let event = rx.recv().await.unwrap();
+        assert!(event.severity.is_finite());
+        let json: serde_json::Value = serde_json::from_str(&event.to_json()).unwrap();
+        assert!(json["severity"].is_f64());
     }
 }
@@ -1,5 +1,7 @@
-use crate::types::{Interval};
+use crate::types::{Interval, Kline};
 use fxhash::hash64;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn align_ts(ts: u64, interval: Interval) -> u64 {
     ts - (ts % (interval.seconds() * 1000)) // Binance E 是毫秒
@@ -8,4 +10,96 @@ pub fn align_ts(ts: u64, interval: Interval) -> u64 {
 // 计算hash,把 symbol hash 到固定 worker
 pub fn assign_worker(symbol: &str, worker_count: usize) -> usize {
     (hash64(symbol.as_bytes()) % worker_count as u64) as usize
-}
\ No newline at end of file
+}
+
+// Binance 默认 8 小时结算一次资金费率（毫秒）
+pub const DEFAULT_FUNDING_INTERVAL: u64 = 8 * 3600 * 1000;
+
+// 年化资金费率，interval 为结算周期（毫秒）
+pub fn annualize_funding_rate(rate: f64, interval: u64) -> f64 {
+    rate * (365.0 * 24.0 * 3600.0 * 1000.0) / interval as f64
+}
+
+// 成交额权重：24小时成交额 1 亿 USDT 为 1，每相差 10 倍增减 0.25，限制在 [0.5, 1.5]
+pub fn turnover_weight(turnover: f64) -> f64 {
+    if turnover <= 0.0 {
+        return 0.5;
+    }
+    (1.0 + (turnover / 1e8).log10() * 0.25).clamp(0.5, 1.5)
+}
+
+// 最近一个周期长度的滚动涨跌幅：以 ts 往前一个周期时所在k线的开盘价为基准
+// 优先使用 5m k线，误差不超过5分钟；5m k线不够时使用该周期自己的k线，历史不足一个周期时返回 None
+pub fn rolling_return(
+    series: &HashMap<Interval, Vec<Kline>>,
+    interval: Interval,
+    ts: u64,
+    price: f64,
+) -> Option<f64> {
+    let start = ts.checked_sub(interval.seconds() * 1000)?;
+    [Interval::Min5, interval].iter().find_map(|source| {
+        let klines = series.get(source)?;
+        // k线按时间排序，最早的k线晚于 start 说明历史不足
+        if klines.first()?.start_ts > start {
+            return None;
+        }
+        let base = klines.iter().rev().find(|k| k.start_ts <= start)?;
+        (base.open > 0.0).then(|| (price - base.open) / base.open)
+    })
+}
+
+// 当前时间戳（毫秒）
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: u64 = 60 * 1000;
+
+    // 从 start 开始每根k线开盘价递增 1
+    fn series(interval: Interval, start: u64, count: u64) -> Vec<Kline> {
+        (0..count)
+            .map(|i| Kline::new(start + i * interval.seconds() * 1000, 100.0 + i as f64, 1.0))
+            .collect()
+    }
+
+    #[test]
+    fn rolling_return_uses_bar_one_interval_ago() {
+        let mut all = HashMap::new();
+        all.insert(Interval::Min5, series(Interval::Min5, 0, 30));
+        // 当前时间 102 分钟：15m 窗口从 87 分钟开始，落在 85 分钟开始的第 17 根 5m k线
+        let r = rolling_return(&all, Interval::Min15, 102 * MIN, 234.0).unwrap();
+        assert!((r - (234.0 - 117.0) / 117.0).abs() < 1e-12);
+        // 跨过 15m 边界后不会归零
+        let r = rolling_return(&all, Interval::Min15, 105 * MIN, 234.0).unwrap();
+        assert!((r - (234.0 - 118.0) / 118.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rolling_return_falls_back_to_own_interval() {
+        let mut all = HashMap::new();
+        // 5m k线只有最近 50 分钟，4h 窗口使用 4h k线
+        all.insert(Interval::Min5, series(Interval::Min5, 500 * MIN, 10));
+        all.insert(Interval::Hour4, series(Interval::Hour4, 0, 3));
+        let r = rolling_return(&all, Interval::Hour4, 540 * MIN, 110.0).unwrap();
+        // 540 - 240 = 300 分钟，落在第 2 根 4h k线（240 分钟开始）
+        assert!((r - (110.0 - 101.0) / 101.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rolling_return_needs_full_window() {
+        let mut all = HashMap::new();
+        all.insert(Interval::Min5, series(Interval::Min5, 100 * MIN, 4));
+        all.insert(Interval::Hour1, series(Interval::Hour1, 60 * MIN, 1));
+        assert_eq!(
+            rolling_return(&all, Interval::Hour1, 118 * MIN, 100.0),
+            None
+        );
+    }
+}
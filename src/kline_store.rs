@@ -0,0 +1,195 @@
+use crate::config::Config as AppConfig;
+use crate::metrics::SinkMetrics;
+use crate::redis::{client_config, LazyClient};
+use crate::sink::EventSink;
+use crate::types::{ClosedBar, Event};
+use anyhow::Result;
+use rustis::resp::{cmd, Command};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+// ========== 收盘k线写入 redis ==========
+// 每个交易对和周期一个有序集合 <namespace>:kline:<symbol>:<interval>，score 为 start_ts，
+// member 为k线的 JSON，只保留最近 server.max_kline_count 根
+// 即发即弃：redis 不可用时丢弃，恢复后从下一根收盘k线继续写入
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+// 每次最多写入的k线数，4h 收盘时所有周期的k线同时收盘
+const BATCH_SIZE: usize = 500;
+
+#[derive(Clone)]
+pub struct KlineStore {
+    tx: mpsc::Sender<ClosedBar>,
+    metrics: Arc<SinkMetrics>,
+}
+
+impl KlineStore {
+    /// 启动写入任务，第一次写入时才连接 redis
+    pub fn spawn(cfg: &AppConfig) -> Result<(Self, JoinHandle<()>)> {
+        let mut config = client_config(&cfg.redis)?;
+        config.command_timeout = COMMAND_TIMEOUT;
+        let metrics = Arc::new(SinkMetrics::new("redis_klines"));
+        let (tx, rx) = mpsc::channel(10000);
+        let writer = Writer {
+            client: LazyClient::new("redis kline store", config),
+            namespace: cfg.redis.namespace.clone(),
+            retention: cfg.server.max_kline_count as usize,
+        };
+        let task = tokio::spawn(store_loop(writer, rx, metrics.clone()));
+        Ok((Self { tx, metrics }, task))
+    }
+}
+
+// 只写入收盘k线，sinks.klines 可以热加载
+impl EventSink for KlineStore {
+    fn send_event(&self, _event: &Arc<Event>, _cfg: &AppConfig) {}
+
+    /// 交给写入任务，任务跟不上时丢弃
+    fn send_kline(&self, bar: &ClosedBar, cfg: &AppConfig) {
+        let klines_cfg = &cfg.sinks.klines;
+        if !klines_cfg.enabled || !klines_cfg.intervals.contains(&bar.interval) {
+            return;
+        }
+        if self.tx.try_send(bar.clone()).is_err() {
+            SinkMetrics::incr(&self.metrics.dropped);
+        }
+    }
+
+    fn metrics(&self) -> Arc<SinkMetrics> {
+        self.metrics.clone()
+    }
+}
+
+struct Writer {
+    client: LazyClient,
+    namespace: String,
+    retention: usize,
+}
+
+impl Writer {
+    // 同一根k线重复写入（如回放）时先删除旧值，写入后删除超出保留数量的旧k线
+    fn commands(&self, bar: &ClosedBar) -> Result<[Command; 3]> {
+        let key = format!("{}:kline:{}:{}", self.namespace, bar.symbol, bar.interval);
+        let start_ts = bar.kline.start_ts;
+        Ok([
+            cmd("ZREMRANGEBYSCORE")
+                .arg(&key)
+                .arg(start_ts)
+                .arg(start_ts),
+            cmd("ZADD")
+                .arg(&key)
+                .arg(start_ts)
+                .arg(serde_json::to_string(&bar.kline)?),
+            cmd("ZREMRANGEBYRANK")
+                .arg(key)
+                .arg(0)
+                .arg(-(self.retention as i64) - 1),
+        ])
+    }
+
+    // 单机和 sentinel 模式用一个事务写入整批，cluster 模式各个 key 不在同一个 slot，用 pipeline
+    async fn write(&mut self, batch: &[ClosedBar]) -> Result<()> {
+        let mut commands = Vec::with_capacity(batch.len() * 3);
+        for bar in batch {
+            commands.extend(self.commands(bar)?);
+        }
+        let cluster = self.client.is_cluster();
+        let client = self.client.get().await?;
+        if cluster {
+            let mut pipeline = client.create_pipeline();
+            for command in commands {
+                pipeline.forget(command);
+            }
+            pipeline.execute::<()>().await?;
+        } else {
+            let mut transaction = client.create_transaction();
+            for command in commands {
+                transaction.forget(command);
+            }
+            transaction.execute::<()>().await?;
+        }
+        Ok(())
+    }
+}
+
+async fn store_loop(
+    mut writer: Writer,
+    mut rx: mpsc::Receiver<ClosedBar>,
+    metrics: Arc<SinkMetrics>,
+) {
+    let mut failing = false;
+    while let Some(first) = rx.recv().await {
+        let mut batch = vec![first];
+        while batch.len() < BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(bar) => batch.push(bar),
+                Err(_) => break,
+            }
+        }
+        let count = batch.len() as u64;
+        match writer.write(&batch).await {
+            Ok(()) => {
+                metrics.written.fetch_add(count, Ordering::Relaxed);
+                if failing {
+                    failing = false;
+                    info!("redis kline store recovered");
+                }
+            }
+            Err(e) => {
+                SinkMetrics::incr(&metrics.failures);
+                metrics.dropped.fetch_add(count, Ordering::Relaxed);
+                if !failing {
+                    failing = true;
+                    error!("redis kline store unavailable, dropping klines: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Interval, Kline};
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .args
+            .iter()
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn replace_and_trim() {
+        let writer = Writer {
+            client: LazyClient::new("test", Default::default()),
+            namespace: "perpx".into(),
+            retention: 100,
+        };
+        let bar = ClosedBar {
+            symbol: "BTCUSDT".into(),
+            interval: Interval::Hour1,
+            kline: Kline::new(1_704_067_200_000, 100.0, 1.0),
+        };
+        let [remove, add, trim] = writer.commands(&bar).unwrap();
+        let key = "perpx:kline:BTCUSDT:1h";
+        let start_ts = "1704067200000";
+
+        // 先删除同一 start_ts 的旧值再写入
+        assert_eq!(remove.name, "ZREMRANGEBYSCORE");
+        assert_eq!(args(&remove), [key, start_ts, start_ts]);
+        assert_eq!(add.name, "ZADD");
+        assert_eq!(
+            args(&add),
+            [key, start_ts, &serde_json::to_string(&bar.kline).unwrap()]
+        );
+        // 保留最近 100 根
+        assert_eq!(trim.name, "ZREMRANGEBYRANK");
+        assert_eq!(args(&trim), [key, "0", "-101"]);
+    }
+}
@@ -0,0 +1,30 @@
+//! Binance 永续合约行情监控
+//!
+//! 作为库使用时主要提供 [`consumer`]：按 <namespace>:queue 协议可靠地消费事件
+
+pub mod app;
+pub mod cli;
+pub mod config;
+pub mod consumer;
+pub mod dedup;
+pub mod dispatcher;
+pub mod encoding;
+pub mod file_sink;
+pub mod handlers;
+pub mod helper;
+pub mod kline_store;
+pub mod listing;
+pub mod market;
+pub mod metrics;
+pub mod nats;
+pub mod postgres;
+pub mod proto;
+pub mod pubsub;
+pub mod redis;
+pub mod reload;
+pub mod server;
+pub mod sink;
+pub mod spool;
+pub mod symbols;
+pub mod types;
+pub mod worker;
@@ -0,0 +1,428 @@
+use crate::config::{ListingConfig, ProxyConfig};
+use crate::types::{EventType, ListingChange, MarketMessage, Message, SymbolInfo};
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<SymbolInfo>,
+}
+
+// fundingInfo 只列出调整过资金费率上下限或结算周期的交易对
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FundingInfo {
+    symbol: String,
+    funding_interval_hours: u64,
+}
+
+// source: http(s) 地址或本地文件路径（便于测试）
+async fn fetch(source: &str, proxy: Option<&ProxyConfig>) -> Result<String> {
+    Ok(
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(30));
+            if let Some(proxy_cfg) = proxy {
+                builder = builder.proxy(reqwest::Proxy::all(format!(
+                    "socks5h://{}",
+                    proxy_cfg.addr
+                ))?);
+            }
+            builder
+                .build()?
+                .get(source)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?
+        } else {
+            tokio::fs::read_to_string(source).await?
+        },
+    )
+}
+
+/// 加载 exchangeInfo 快照
+pub async fn load_exchange_info(
+    source: &str,
+    proxy: Option<&ProxyConfig>,
+) -> Result<HashMap<String, SymbolInfo>> {
+    let content = fetch(source, proxy).await?;
+    let info: ExchangeInfo = serde_json::from_str(&content)?;
+    Ok(info
+        .symbols
+        .into_iter()
+        .map(|s| (s.symbol.clone(), s))
+        .collect())
+}
+
+// 定期刷新 exchangeInfo，交给全市场聚合器比较交易对状态变化
+pub async fn exchange_info_refresher(
+    config: ListingConfig,
+    proxy: Option<ProxyConfig>,
+    market_tx: mpsc::Sender<MarketMessage>,
+) {
+    if config.refresh_interval == 0 {
+        return;
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.refresh_interval));
+    // 启动时已经加载过一次
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        match load_exchange_info(&config.exchange_info, proxy.as_ref()).await {
+            Ok(symbols) => {
+                info!("exchangeInfo refreshed: {} symbols", symbols.len());
+                if market_tx
+                    .send(MarketMessage::ExchangeInfo(symbols))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            Err(e) => error!("failed to refresh exchangeInfo: {:?}", e),
+        }
+    }
+}
+
+/// 加载 fundingInfo，返回各交易对的结算周期（毫秒）
+pub async fn load_funding_info(
+    source: &str,
+    proxy: Option<&ProxyConfig>,
+) -> Result<HashMap<String, u64>> {
+    let content = fetch(source, proxy).await?;
+    let info: Vec<FundingInfo> = serde_json::from_str(&content)?;
+    Ok(info
+        .into_iter()
+        .filter(|f| f.funding_interval_hours > 0)
+        .map(|f| (f.symbol, f.funding_interval_hours * 3600 * 1000))
+        .collect())
+}
+
+// 启动时和每次刷新时加载 fundingInfo，发给所有 worker，不用等两次结算才确定结算周期
+pub async fn funding_info_refresher(
+    config: ListingConfig,
+    proxy: Option<ProxyConfig>,
+    workers: Arc<Vec<mpsc::Sender<Message>>>,
+) {
+    if config.funding_info.is_empty() {
+        return;
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.refresh_interval.max(1)));
+    loop {
+        ticker.tick().await;
+        match load_funding_info(&config.funding_info, proxy.as_ref()).await {
+            Ok(intervals) => {
+                info!("fundingInfo loaded: {} symbols", intervals.len());
+                let intervals = Arc::new(intervals);
+                for worker in workers.iter() {
+                    if worker
+                        .send(Message::FundingInfo(intervals.clone()))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+            Err(e) => error!("failed to load fundingInfo: {:?}", e),
+        }
+        if config.refresh_interval == 0 {
+            break;
+        }
+    }
+}
+
+// 交易对上线/下架跟踪，在全市场聚合器中使用
+pub struct ListingTracker {
+    known: Option<HashMap<String, SymbolInfo>>, // exchangeInfo 快照，加载失败时为 None
+    first_seen: HashMap<String, u64>,
+    last_seen: HashMap<String, u64>,
+    delisted: HashSet<String>,
+    delist_after: u64,
+}
+
+impl ListingTracker {
+    pub fn new(known: Option<HashMap<String, SymbolInfo>>, config: &ListingConfig) -> Self {
+        Self {
+            known,
+            first_seen: HashMap::new(),
+            last_seen: HashMap::new(),
+            delisted: HashSet::new(),
+            delist_after: config.delist_after,
+        }
+    }
+
+    // 收到 ticker，不在 exchangeInfo 快照中或下架后重新出现时视为新上线
+    pub fn on_ticker(&mut self, symbol: &str, event_time: u64) -> Option<ListingChange> {
+        self.last_seen.insert(symbol.to_string(), event_time);
+        let relisted = self.delisted.remove(symbol);
+        if self.first_seen.contains_key(symbol) && !relisted {
+            return None;
+        }
+        self.first_seen.insert(symbol.to_string(), event_time);
+
+        let known = self.known.as_ref()?;
+        let info = known.get(symbol);
+        if info.is_some() && !relisted {
+            return None;
+        }
+        Some(ListingChange {
+            reason: if relisted { "relisted" } else { "first_seen" }.to_string(),
+            status: info.map(|i| i.status.clone()),
+            prev_status: None,
+            first_seen: Some(event_time),
+            last_seen: Some(event_time),
+            onboard_date: info.map(|i| i.onboard_date),
+        })
+    }
+
+    // exchangeInfo 刷新：新增交易对视为上线，状态从交易中变为其他状态视为下架
+    pub fn on_exchange_info(
+        &mut self,
+        symbols: HashMap<String, SymbolInfo>,
+    ) -> Vec<(String, EventType, ListingChange)> {
+        let mut changes = Vec::new();
+        if let Some(known) = &self.known {
+            for (symbol, info) in &symbols {
+                let prev = known.get(symbol);
+                let change = ListingChange {
+                    reason: "".to_string(),
+                    status: Some(info.status.clone()),
+                    prev_status: prev.map(|p| p.status.clone()),
+                    first_seen: self.first_seen.get(symbol).copied(),
+                    last_seen: self.last_seen.get(symbol).copied(),
+                    onboard_date: Some(info.onboard_date),
+                };
+                match prev {
+                    // ticker 中已经出现过的交易对在 on_ticker 中已经发过事件
+                    None if is_active(&info.status) && !self.first_seen.contains_key(symbol) => {
+                        changes.push((
+                            symbol.clone(),
+                            EventType::NewListing,
+                            ListingChange {
+                                reason: "exchange_info".to_string(),
+                                ..change
+                            },
+                        ));
+                    }
+                    Some(p) if is_active(&p.status) && !is_active(&info.status) => {
+                        self.delisted.insert(symbol.clone());
+                        changes.push((
+                            symbol.clone(),
+                            EventType::Delisting,
+                            ListingChange {
+                                reason: "status".to_string(),
+                                ..change
+                            },
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.known = Some(symbols);
+        changes
+    }
+
+    // 长时间没有出现在 ticker 中的交易对视为下架
+    pub fn check_absent(&mut self, latest: u64) -> Vec<(String, ListingChange)> {
+        let mut changes = Vec::new();
+        for (symbol, &last_seen) in &self.last_seen {
+            if latest.saturating_sub(last_seen) < self.delist_after * 1000
+                || self.delisted.contains(symbol)
+            {
+                continue;
+            }
+            self.delisted.insert(symbol.clone());
+            let info = self.known.as_ref().and_then(|k| k.get(symbol));
+            changes.push((
+                symbol.clone(),
+                ListingChange {
+                    reason: "not_seen".to_string(),
+                    status: info.map(|i| i.status.clone()),
+                    prev_status: None,
+                    first_seen: self.first_seen.get(symbol).copied(),
+                    last_seen: Some(last_seen),
+                    onboard_date: info.map(|i| i.onboard_date),
+                },
+            ));
+        }
+        changes
+    }
+}
+
+// 交易中或即将上线
+fn is_active(status: &str) -> bool {
+    status == "TRADING" || status == "PENDING_TRADING"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: u64 = 60 * 1000;
+
+    // 写到临时文件，测试从本地文件加载快照
+    fn temp_file(name: &str, content: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("perpx-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    async fn exchange_info(name: &str, statuses: &[(&str, &str)]) -> HashMap<String, SymbolInfo> {
+        let symbols = statuses
+            .iter()
+            .map(|(symbol, status)| {
+                format!(
+                    r#"{{"symbol":"{}","status":"{}","contractType":"PERPETUAL","onboardDate":1569398400000,"pricePrecision":2}}"#,
+                    symbol, status
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let path = temp_file(
+            name,
+            &format!(r#"{{"timezone":"UTC","symbols":[{}]}}"#, symbols),
+        );
+        let info = load_exchange_info(path.to_str().unwrap(), None)
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        info
+    }
+
+    async fn tracker() -> ListingTracker {
+        let known = exchange_info(
+            "exchange-info",
+            &[("BTCUSDT", "TRADING"), ("ETHUSDT", "TRADING")],
+        )
+        .await;
+        assert_eq!(known["BTCUSDT"].onboard_date, 1569398400000);
+        ListingTracker::new(Some(known), &ListingConfig::default())
+    }
+
+    #[tokio::test]
+    async fn new_listing_from_ticker() {
+        let mut tracker = tracker().await;
+        assert!(tracker.on_ticker("BTCUSDT", MIN).is_none());
+        let change = tracker.on_ticker("NEWUSDT", MIN).unwrap();
+        assert_eq!(change.reason, "first_seen");
+        assert_eq!((change.status, change.first_seen), (None, Some(MIN)));
+        assert!(tracker.on_ticker("NEWUSDT", 2 * MIN).is_none());
+
+        // exchangeInfo 刷新后不再重复发送，新出现在 exchangeInfo 中的交易对视为上线
+        let changes = tracker.on_exchange_info(
+            exchange_info(
+                "exchange-info-new",
+                &[
+                    ("BTCUSDT", "TRADING"),
+                    ("ETHUSDT", "TRADING"),
+                    ("NEWUSDT", "TRADING"),
+                    ("SOONUSDT", "PENDING_TRADING"),
+                    ("OLDUSDT", "SETTLING"),
+                ],
+            )
+            .await,
+        );
+        assert_eq!(changes.len(), 1);
+        let (symbol, event_type, change) = &changes[0];
+        assert_eq!(
+            (symbol.as_str(), *event_type),
+            ("SOONUSDT", EventType::NewListing)
+        );
+        assert_eq!(change.reason, "exchange_info");
+        assert_eq!(change.status.as_deref(), Some("PENDING_TRADING"));
+    }
+
+    #[tokio::test]
+    async fn delisting_on_status_change_and_relisting() {
+        let mut tracker = tracker().await;
+        tracker.on_ticker("BTCUSDT", MIN);
+        tracker.on_ticker("ETHUSDT", MIN);
+
+        let changes = tracker.on_exchange_info(
+            exchange_info(
+                "exchange-info-settling",
+                &[("BTCUSDT", "TRADING"), ("ETHUSDT", "SETTLING")],
+            )
+            .await,
+        );
+        assert_eq!(changes.len(), 1);
+        let (symbol, event_type, change) = &changes[0];
+        assert_eq!(
+            (symbol.as_str(), *event_type),
+            ("ETHUSDT", EventType::Delisting)
+        );
+        assert_eq!(change.reason, "status");
+        assert_eq!(change.prev_status.as_deref(), Some("TRADING"));
+        assert_eq!(change.status.as_deref(), Some("SETTLING"));
+        assert_eq!(change.last_seen, Some(MIN));
+
+        // 已经下架的交易对不会因为长时间没有行情再次发送
+        let absent = tracker.check_absent(MIN + 1800 * 1000);
+        assert_eq!(absent.len(), 1);
+        assert_eq!(absent[0].0, "BTCUSDT");
+
+        // 下架后重新出现在 ticker 中
+        let change = tracker.on_ticker("ETHUSDT", 100 * MIN).unwrap();
+        assert_eq!(change.reason, "relisted");
+        assert_eq!(change.status.as_deref(), Some("SETTLING"));
+        assert!(tracker.on_ticker("ETHUSDT", 101 * MIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn delisting_when_absent() {
+        let mut tracker = tracker().await;
+        tracker.on_ticker("BTCUSDT", MIN);
+        tracker.on_ticker("ETHUSDT", MIN);
+        tracker.on_ticker("BTCUSDT", 20 * MIN);
+
+        // delist_after 默认1800秒
+        assert!(tracker.check_absent(30 * MIN).is_empty());
+        let changes = tracker.check_absent(31 * MIN);
+        assert_eq!(changes.len(), 1);
+        let (symbol, change) = &changes[0];
+        assert_eq!(symbol, "ETHUSDT");
+        assert_eq!(change.reason, "not_seen");
+        assert_eq!(change.last_seen, Some(MIN));
+        assert_eq!(change.status.as_deref(), Some("TRADING"));
+        assert!(tracker.check_absent(40 * MIN).is_empty());
+
+        assert_eq!(
+            tracker.on_ticker("ETHUSDT", 41 * MIN).unwrap().reason,
+            "relisted"
+        );
+        assert!(tracker.check_absent(45 * MIN).is_empty());
+    }
+
+    #[test]
+    fn no_snapshot() {
+        // exchangeInfo 加载失败时不根据 ticker 判断上线
+        let mut tracker = ListingTracker::new(None, &ListingConfig::default());
+        assert!(tracker.on_ticker("NEWUSDT", MIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn funding_info_intervals() {
+        let path = temp_file(
+            "funding",
+            r#"[
+                {"symbol":"BLZUSDT","adjustedFundingRateCap":"0.02500000","adjustedFundingRateFloor":"-0.02500000","fundingIntervalHours":4,"disclaimer":false},
+                {"symbol":"1000PEPEUSDT","adjustedFundingRateCap":"0.03000000","adjustedFundingRateFloor":"-0.03000000","fundingIntervalHours":1,"disclaimer":false}
+            ]"#,
+        );
+        let intervals = load_funding_info(path.to_str().unwrap(), None)
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(intervals.len(), 2);
+        assert_eq!(intervals["BLZUSDT"], 4 * 3600 * 1000);
+        assert_eq!(intervals["1000PEPEUSDT"], 3600 * 1000);
+    }
+}
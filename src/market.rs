@@ -0,0 +1,573 @@
+use crate::config::{ConfigReceiver, MarketConfig};
+use crate::dispatcher::EventSender;
+use crate::handlers::listing_handler::process_listing_change;
+use crate::handlers::market_handler::{
+    process_btc_divergence, process_market_breadth, process_market_ranking,
+};
+use crate::listing::ListingTracker;
+use crate::types::{
+    BreadthState, BtcDivergence, ClosedBar, EventType, Interval, MarketBreadth, MarketMessage,
+    MarketRanking, RankChange, RankEntry, SymbolSnapshot,
+};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+
+// 超过5分钟没有更新的交易对不参与排行（已下架或暂停交易）
+const STALE_MS: u64 = 5 * 60 * 1000;
+// 周期结束后等待各交易对收盘的时间
+const BAR_GRACE_MS: u64 = 10 * 1000;
+
+// ========== 全市场聚合 ==========
+// worker 按 symbol 分片，各自只持有部分交易对，这里汇总所有 worker 的快照做跨交易对计算
+pub async fn market_aggregator(
+    mut rx: mpsc::Receiver<MarketMessage>,
+    mut config_rx: ConfigReceiver,
+    mut listing: ListingTracker,
+    events: EventSender,
+) {
+    let mut config = config_rx.borrow_and_update().market.clone();
+    let mut snapshots: HashMap<String, SymbolSnapshot> = HashMap::new();
+    let mut prev_turnover_ranks: HashMap<String, usize> = HashMap::new();
+    let mut breadth = BreadthTracker::default();
+    let mut latest: u64 = 0; // 最新的行情时间
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.ranking_interval.max(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    // 第一次 tick 立即触发，此时还没有数据，跳过
+    ticker.tick().await;
+    let mut bar_ticker = tokio::time::interval(Duration::from_secs(1));
+    bar_ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(MarketMessage::Snapshot(snapshot)) => {
+                    latest = latest.max(snapshot.event_time);
+                    if let Some(change) = listing.on_ticker(&snapshot.symbol, snapshot.event_time) {
+                        let symbol = snapshot.symbol.clone();
+                        let timestamp = snapshot.event_time;
+                        let events_clone = events.clone();
+                        tokio::spawn(async move {
+                            process_listing_change(symbol, EventType::NewListing, timestamp, change, events_clone)
+                                .await;
+                        });
+                    }
+                    breadth.started_at.get_or_insert(snapshot.event_time);
+                    snapshots.insert(snapshot.symbol.clone(), snapshot);
+                }
+                Some(MarketMessage::BarClosed(bar)) => breadth.on_bar(bar),
+                Some(MarketMessage::ExchangeInfo(symbols)) => {
+                    for (symbol, event_type, change) in listing.on_exchange_info(symbols) {
+                        let events_clone = events.clone();
+                        tokio::spawn(async move {
+                            process_listing_change(symbol, event_type, latest, change, events_clone).await;
+                        });
+                    }
+                }
+                None => break,
+            },
+            _ = bar_ticker.tick() => {
+                for (symbol, change) in listing.check_absent(latest) {
+                    let events_clone = events.clone();
+                    tokio::spawn(async move {
+                        process_listing_change(symbol, EventType::Delisting, latest, change, events_clone)
+                            .await;
+                    });
+                }
+                for (interval, start_ts, stats, divergences) in breadth.finalize(latest, &config) {
+                    let timestamp = start_ts;
+                    if let Some(stats) = stats {
+                        let severity = stats.up_ratio.max(stats.down_ratio) / config.breadth_extreme;
+                        let events_clone = events.clone();
+                        tokio::spawn(async move {
+                            process_market_breadth(interval, timestamp, stats, severity, events_clone)
+                                .await;
+                        });
+                    }
+                    for (symbol, divergence) in divergences {
+                        let severity = divergence.z_score.abs() / config.divergence_z;
+                        let events_clone = events.clone();
+                        tokio::spawn(async move {
+                            process_btc_divergence(
+                                symbol,
+                                interval,
+                                timestamp,
+                                divergence,
+                                severity,
+                                events_clone,
+                            )
+                            .await;
+                        });
+                    }
+                }
+            }
+            Ok(()) = config_rx.changed() => {
+                let new_config = config_rx.borrow_and_update().market.clone();
+                // 排行间隔变化时重建定时器
+                if new_config.ranking_interval != config.ranking_interval {
+                    let period = Duration::from_secs(new_config.ranking_interval.max(1));
+                    ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                }
+                config = new_config;
+            }
+            _ = ticker.tick() => {
+                snapshots.retain(|_, s| latest - s.event_time <= STALE_MS);
+                if snapshots.is_empty() {
+                    continue;
+                }
+                let ranking = compute_ranking(&snapshots, &mut prev_turnover_ranks, &config);
+                let events_clone = events.clone();
+                tokio::spawn(async move {
+                    process_market_ranking(latest, ranking, events_clone).await;
+                });
+            }
+        }
+    }
+}
+
+// 排序，数值相同时按交易对名称排序，保证榜单稳定
+fn sort_entries(
+    entries: &mut [RankEntry],
+    by_value: impl Fn(&RankEntry, &RankEntry) -> Option<Ordering>,
+) {
+    entries.sort_by(|a, b| {
+        by_value(a, b)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.symbol.cmp(&b.symbol))
+    });
+}
+
+fn compute_ranking(
+    snapshots: &HashMap<String, SymbolSnapshot>,
+    prev_turnover_ranks: &mut HashMap<String, usize>,
+    config: &MarketConfig,
+) -> MarketRanking {
+    let mut gainers = HashMap::new();
+    let mut losers = HashMap::new();
+    for &interval in &config.intervals {
+        let mut returns = snapshots
+            .values()
+            .filter_map(|s| {
+                let (_, r) = s.returns.iter().find(|(i, _)| *i == interval)?;
+                Some(RankEntry {
+                    symbol: s.symbol.clone(),
+                    price: s.price,
+                    value: *r,
+                })
+            })
+            .collect::<Vec<RankEntry>>();
+        sort_entries(&mut returns, |a, b| b.value.partial_cmp(&a.value));
+        gainers.insert(
+            interval,
+            returns
+                .iter()
+                .filter(|e| e.value > 0.0)
+                .take(config.top_n)
+                .cloned()
+                .collect(),
+        );
+        sort_entries(&mut returns, |a, b| a.value.partial_cmp(&b.value));
+        losers.insert(
+            interval,
+            returns
+                .iter()
+                .filter(|e| e.value < 0.0)
+                .take(config.top_n)
+                .cloned()
+                .collect(),
+        );
+    }
+
+    // 成交额排名，名次从1开始
+    let mut by_turnover = snapshots
+        .values()
+        .map(|s| RankEntry {
+            symbol: s.symbol.clone(),
+            price: s.price,
+            value: s.turnover,
+        })
+        .collect::<Vec<RankEntry>>();
+    sort_entries(&mut by_turnover, |a, b| b.value.partial_cmp(&a.value));
+    let ranks = by_turnover
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (e.symbol.clone(), i + 1))
+        .collect::<HashMap<String, usize>>();
+
+    let mut turnover_rank_changes = ranks
+        .iter()
+        .filter_map(|(symbol, &rank)| {
+            let &prev_rank = prev_turnover_ranks.get(symbol)?;
+            let change = prev_rank as i64 - rank as i64;
+            (change != 0).then(|| RankChange {
+                symbol: symbol.clone(),
+                rank,
+                prev_rank,
+                change,
+            })
+        })
+        .collect::<Vec<RankChange>>();
+    turnover_rank_changes.sort_by_key(|c| (std::cmp::Reverse(c.change.abs()), c.rank));
+    turnover_rank_changes.truncate(config.top_n);
+    *prev_turnover_ranks = ranks;
+
+    by_turnover.truncate(config.top_n);
+    MarketRanking {
+        symbol_count: snapshots.len(),
+        gainers,
+        losers,
+        top_turnover: by_turnover,
+        turnover_rank_changes,
+    }
+}
+
+// ========== 市场宽度与相对强弱 ==========
+#[derive(Default)]
+struct BreadthTracker {
+    started_at: Option<u64>, // 第一个行情时间，之前开始的k线不完整，不参与计算
+    buckets: HashMap<(Interval, u64), HashMap<String, f64>>, // (周期, k线开始时间) -> 各交易对涨跌幅
+    states: HashMap<Interval, BreadthState>,
+    relative: HashMap<(String, Interval), VecDeque<(f64, f64)>>, // (交易对涨跌幅, 基准涨跌幅)
+}
+
+// (周期, k线开始时间, 进入极端时的市场宽度, 背离的交易对)
+type BucketResult = (
+    Interval,
+    u64,
+    Option<MarketBreadth>,
+    Vec<(String, BtcDivergence)>,
+);
+
+impl BreadthTracker {
+    fn on_bar(&mut self, bar: ClosedBar) {
+        let Some(started_at) = self.started_at else {
+            return;
+        };
+        if bar.kline.start_ts < started_at || bar.kline.open <= 0.0 {
+            return;
+        }
+        let r = (bar.kline.close - bar.kline.open) / bar.kline.open;
+        self.buckets
+            .entry((bar.interval, bar.kline.start_ts))
+            .or_default()
+            .insert(bar.symbol, r);
+    }
+
+    // 结算已经结束的周期
+    fn finalize(&mut self, latest: u64, config: &MarketConfig) -> Vec<BucketResult> {
+        let mut due = self
+            .buckets
+            .keys()
+            .filter(|(interval, start_ts)| {
+                latest >= start_ts + interval.seconds() * 1000 + BAR_GRACE_MS
+            })
+            .copied()
+            .collect::<Vec<(Interval, u64)>>();
+        due.sort_by_key(|(_, start_ts)| *start_ts);
+
+        let mut results = Vec::new();
+        for key in due {
+            let returns = self.buckets.remove(&key).unwrap_or_default();
+            let (interval, start_ts) = key;
+            let stats = self.update_breadth(interval, &returns, config);
+            let divergences = self.update_relative(interval, &returns, config);
+            results.push((interval, start_ts, stats, divergences));
+        }
+        results
+    }
+
+    // 计算市场宽度，只在状态切换到极端时返回
+    fn update_breadth(
+        &mut self,
+        interval: Interval,
+        returns: &HashMap<String, f64>,
+        config: &MarketConfig,
+    ) -> Option<MarketBreadth> {
+        let total = returns.len();
+        if total == 0 || total < config.min_breadth_symbols {
+            return None;
+        }
+        let up_count = returns.values().filter(|r| **r > 0.0).count();
+        let down_count = returns.values().filter(|r| **r < 0.0).count();
+        let up_ratio = up_count as f64 / total as f64;
+        let down_ratio = down_count as f64 / total as f64;
+        let state = if up_ratio >= config.breadth_extreme {
+            BreadthState::Bullish
+        } else if down_ratio >= config.breadth_extreme {
+            BreadthState::Bearish
+        } else {
+            BreadthState::Neutral
+        };
+        let prev = self.states.insert(interval, state);
+        if state == BreadthState::Neutral || prev == Some(state) {
+            return None;
+        }
+        Some(MarketBreadth {
+            state,
+            symbol_count: total,
+            up_count,
+            down_count,
+            up_ratio,
+            down_ratio,
+            avg_return: returns.values().sum::<f64>() / total as f64,
+        })
+    }
+
+    // 更新各交易对相对基准的滚动窗口，返回超额收益超过阈值的交易对
+    fn update_relative(
+        &mut self,
+        interval: Interval,
+        returns: &HashMap<String, f64>,
+        config: &MarketConfig,
+    ) -> Vec<(String, BtcDivergence)> {
+        let Some(&btc_return) = returns.get(&config.benchmark) else {
+            return Vec::new();
+        };
+        let mut divergences = Vec::new();
+        for (symbol, &symbol_return) in returns {
+            if *symbol == config.benchmark {
+                continue;
+            }
+            let samples = self.relative.entry((symbol.clone(), interval)).or_default();
+            // 用历史窗口估计 beta，再衡量当前k线
+            if samples.len() >= config.min_beta_samples.max(2) {
+                if let Some((beta, mean, std)) = residual_stats(samples) {
+                    let residual = symbol_return - beta * btc_return;
+                    let z_score = (residual - mean) / std;
+                    if z_score.abs() >= config.divergence_z {
+                        divergences.push((
+                            symbol.clone(),
+                            BtcDivergence {
+                                symbol_return,
+                                btc_return,
+                                beta,
+                                residual,
+                                z_score,
+                            },
+                        ));
+                    }
+                }
+            }
+            samples.push_back((symbol_return, btc_return));
+            while samples.len() > config.beta_window {
+                samples.pop_front();
+            }
+        }
+        divergences
+    }
+}
+
+// 返回 (beta, 超额收益均值, 超额收益标准差)
+fn residual_stats(samples: &VecDeque<(f64, f64)>) -> Option<(f64, f64, f64)> {
+    let n = samples.len() as f64;
+    let mean_s = samples.iter().map(|(s, _)| s).sum::<f64>() / n;
+    let mean_b = samples.iter().map(|(_, b)| b).sum::<f64>() / n;
+    let cov = samples
+        .iter()
+        .map(|(s, b)| (s - mean_s) * (b - mean_b))
+        .sum::<f64>()
+        / n;
+    let var_b = samples
+        .iter()
+        .map(|(_, b)| (b - mean_b).powi(2))
+        .sum::<f64>()
+        / n;
+    if var_b <= 0.0 {
+        return None;
+    }
+    let beta = cov / var_b;
+    let residuals = samples
+        .iter()
+        .map(|(s, b)| s - beta * b)
+        .collect::<Vec<f64>>();
+    let mean = residuals.iter().sum::<f64>() / n;
+    let std = (residuals.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n).sqrt();
+    if std <= 0.0 {
+        return None;
+    }
+    Some((beta, mean, std))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Kline;
+
+    const BAR_MS: u64 = 5 * 60 * 1000;
+
+    fn bar(symbol: &str, start_ts: u64, ret: f64) -> ClosedBar {
+        ClosedBar {
+            symbol: symbol.to_string(),
+            interval: Interval::Min5,
+            kline: Kline {
+                open: 100.0,
+                high: 100.0 * (1.0 + ret.max(0.0)),
+                low: 100.0 * (1.0 + ret.min(0.0)),
+                close: 100.0 * (1.0 + ret),
+                volume: 1.0,
+                start_ts,
+            },
+        }
+    }
+
+    fn config() -> MarketConfig {
+        MarketConfig {
+            breadth_extreme: 0.75,
+            min_breadth_symbols: 4,
+            ..Default::default()
+        }
+    }
+
+    // 第 n 根k线，returns 依次对应 A/B/C/D 四个交易对
+    fn close_bars(
+        tracker: &mut BreadthTracker,
+        n: u64,
+        returns: [f64; 4],
+    ) -> Option<MarketBreadth> {
+        for (symbol, r) in ["AUSDT", "BUSDT", "CUSDT", "DUSDT"].iter().zip(returns) {
+            tracker.on_bar(bar(symbol, n * BAR_MS, r));
+        }
+        let mut results = tracker.finalize((n + 1) * BAR_MS + BAR_GRACE_MS, &config());
+        assert_eq!(results.len(), 1);
+        results.pop().unwrap().2
+    }
+
+    #[test]
+    fn breadth_reported_on_state_change() {
+        let mut tracker = BreadthTracker {
+            started_at: Some(0),
+            ..Default::default()
+        };
+
+        // 周期结束前不结算
+        tracker.on_bar(bar("AUSDT", 0, 0.01));
+        assert!(tracker.finalize(BAR_MS, &config()).is_empty());
+
+        let stats = close_bars(&mut tracker, 0, [0.01, 0.02, 0.03, -0.01]).unwrap();
+        assert_eq!(stats.state, BreadthState::Bullish);
+        assert_eq!((stats.up_count, stats.down_count), (3, 1));
+        assert!((stats.avg_return - 0.0125).abs() < 1e-9);
+
+        // 保持极端状态不重复发送，回到中性后再次进入才发送
+        assert!(close_bars(&mut tracker, 1, [0.01, 0.01, 0.01, 0.01]).is_none());
+        assert!(close_bars(&mut tracker, 2, [0.01, 0.01, -0.01, -0.01]).is_none());
+        let stats = close_bars(&mut tracker, 3, [-0.01, -0.01, -0.01, 0.0]).unwrap();
+        assert_eq!(stats.state, BreadthState::Bearish);
+    }
+
+    #[test]
+    fn breadth_skips_incomplete_bars() {
+        let mut tracker = BreadthTracker::default();
+        // 还没有行情时间
+        tracker.on_bar(bar("AUSDT", 0, 0.01));
+        assert!(tracker.buckets.is_empty());
+
+        // 启动之前开始的k线不完整
+        tracker.started_at = Some(BAR_MS / 2);
+        tracker.on_bar(bar("AUSDT", 0, 0.01));
+        assert!(tracker.buckets.is_empty());
+
+        // 交易对数量不足
+        for symbol in ["AUSDT", "BUSDT", "CUSDT"] {
+            tracker.on_bar(bar(symbol, BAR_MS, 0.01));
+        }
+        let results = tracker.finalize(2 * BAR_MS + BAR_GRACE_MS, &config());
+        assert_eq!(results.len(), 1);
+        assert!(results[0].2.is_none());
+    }
+
+    #[test]
+    fn btc_divergence() {
+        let mut tracker = BreadthTracker {
+            started_at: Some(0),
+            ..Default::default()
+        };
+        let config = MarketConfig {
+            min_beta_samples: 5,
+            ..config()
+        };
+        // ALT 约为 BTC 的两倍，带少量噪声
+        for n in 0..10u64 {
+            let btc = if n % 2 == 0 { 0.01 } else { -0.005 };
+            let noise = if n % 3 == 0 { 0.0005 } else { -0.0005 };
+            tracker.on_bar(bar("BTCUSDT", n * BAR_MS, btc));
+            tracker.on_bar(bar("ALTUSDT", n * BAR_MS, 2.0 * btc + noise));
+            let results = tracker.finalize((n + 1) * BAR_MS + BAR_GRACE_MS, &config);
+            assert!(results[0].3.is_empty());
+        }
+
+        tracker.on_bar(bar("BTCUSDT", 10 * BAR_MS, 0.01));
+        tracker.on_bar(bar("ALTUSDT", 10 * BAR_MS, -0.05));
+        let results = tracker.finalize(11 * BAR_MS + BAR_GRACE_MS, &config);
+        let (symbol, divergence) = &results[0].3[0];
+        assert_eq!(symbol, "ALTUSDT");
+        assert!((divergence.beta - 2.0).abs() < 0.1);
+        assert!(divergence.z_score <= -config.divergence_z);
+    }
+
+    fn snapshot(symbol: &str, turnover: f64, ret: f64) -> (String, SymbolSnapshot) {
+        let snapshot = SymbolSnapshot {
+            symbol: symbol.to_string(),
+            event_time: 0,
+            price: 1.0,
+            turnover,
+            returns: vec![(Interval::Hour1, ret)],
+        };
+        (symbol.to_string(), snapshot)
+    }
+
+    fn symbols(entries: &[RankEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.symbol.as_str()).collect()
+    }
+
+    #[test]
+    fn ranking_top_n_and_ties() {
+        let config = MarketConfig {
+            top_n: 2,
+            intervals: vec![Interval::Hour1, Interval::Hour4],
+            ..Default::default()
+        };
+        let mut snapshots = HashMap::from([
+            snapshot("AUSDT", 300.0, 0.05),
+            snapshot("BUSDT", 100.0, 0.02),
+            snapshot("CUSDT", 300.0, 0.02),
+            snapshot("DUSDT", 200.0, -0.03),
+            snapshot("EUSDT", 50.0, -0.01),
+            snapshot("FUSDT", 400.0, 0.0),
+        ]);
+        let mut prev_ranks = HashMap::new();
+
+        let ranking = compute_ranking(&snapshots, &mut prev_ranks, &config);
+        assert_eq!(ranking.symbol_count, 6);
+        // 涨跌幅相同时按交易对名称排序，不涨不跌的不上榜
+        assert_eq!(
+            symbols(&ranking.gainers[&Interval::Hour1]),
+            ["AUSDT", "BUSDT"]
+        );
+        assert_eq!(
+            symbols(&ranking.losers[&Interval::Hour1]),
+            ["DUSDT", "EUSDT"]
+        );
+        // 没有该周期涨跌幅的交易对不参与排行
+        assert!(ranking.gainers[&Interval::Hour4].is_empty());
+        assert_eq!(symbols(&ranking.top_turnover), ["FUSDT", "AUSDT"]);
+        assert!(ranking.turnover_rank_changes.is_empty());
+        assert_eq!(prev_ranks["CUSDT"], 3);
+
+        // 成交额排名变化按变化幅度排序
+        snapshots.insert("EUSDT".into(), snapshot("EUSDT", 500.0, -0.01).1);
+        snapshots.insert("BUSDT".into(), snapshot("BUSDT", 250.0, 0.02).1);
+        let ranking = compute_ranking(&snapshots, &mut prev_ranks, &config);
+        assert_eq!(symbols(&ranking.top_turnover), ["EUSDT", "FUSDT"]);
+        let changes = ranking
+            .turnover_rank_changes
+            .iter()
+            .map(|c| (c.symbol.as_str(), c.prev_rank, c.rank, c.change))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [("EUSDT", 6, 1, 5), ("DUSDT", 4, 6, -2)]);
+    }
+}
@@ -0,0 +1,51 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// ========== sink 指标 ==========
+// sink 写出任务更新，/metrics 接口和分发器的定时日志读取
+pub struct SinkMetrics {
+    name: &'static str,
+    pub written: AtomicU64,  // 成功写出的消息数
+    pub buffered: AtomicU64, // 当前缓冲中的消息数（内存 + 磁盘）
+    pub spilled: AtomicU64,  // 写到磁盘缓冲的消息数
+    pub dropped: AtomicU64,  // 缓冲满后丢弃的消息数
+    pub failures: AtomicU64, // 写出失败的次数
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SinkStats {
+    pub name: &'static str,
+    pub written: u64,
+    pub buffered: u64,
+    pub spilled: u64,
+    pub dropped: u64,
+    pub failures: u64,
+}
+
+impl SinkMetrics {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            written: AtomicU64::new(0),
+            buffered: AtomicU64::new(0),
+            spilled: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> SinkStats {
+        SinkStats {
+            name: self.name,
+            written: self.written.load(Ordering::Relaxed),
+            buffered: self.buffered.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
+}
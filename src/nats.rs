@@ -0,0 +1,381 @@
+use crate::config::{Config as AppConfig, NatsConfig};
+use crate::encoding::Encoding;
+use crate::metrics::SinkMetrics;
+use crate::sink::EventSink;
+use crate::types::{ClosedBar, Event};
+use anyhow::{anyhow, Result};
+use async_nats::jetstream::context::{Publish, PublishError, PublishErrorKind};
+use async_nats::jetstream::{self, stream};
+use async_nats::{Client, ConnectOptions, HeaderMap};
+use futures_util::future::join_all;
+use std::future::IntoFuture;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+// ========== NATS JetStream ==========
+// 事件发布到 <prefix>.events.<type>.<symbol>，全市场事件的 symbol 为 market
+// 收盘k线发布到 <prefix>.klines.<symbol>.<interval>，固定为 JSON
+// 消息带 Nats-Msg-Id（事件 id，k线为 <symbol>.<interval>.<start_ts>），重试时由 JetStream 去重
+// nats 不可用时在内存中缓冲 buffer_size 条消息，超过后丢弃新消息
+// 被 JetStream 拒绝的消息（超过大小限制、stream 配置不允许等）重试也不会成功，记录错误后丢弃
+
+// 消息编码，和 redis 消息 key 中的编码名称一致
+const ENCODING_HEADER: &str = "Perpx-Encoding";
+// 每次最多发布的消息数，全部确认后再发布下一批
+const BATCH_SIZE: usize = 500;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// 一批消息从发布到全部确认的超时，断线时客户端的发送缓冲写满会一直等待
+const WRITE_TIMEOUT: Duration = Duration::from_secs(15);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// 退出时最多等待多久把缓冲写完
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Message {
+    subject: String,
+    id: String,
+    encoding: Encoding,
+    payload: Vec<u8>,
+}
+
+pub struct NatsSink {
+    tx: mpsc::Sender<Message>,
+    prefix: String,
+    metrics: Arc<SinkMetrics>,
+}
+
+impl NatsSink {
+    /// 启动发布任务，第一次发布时才连接 nats 并检查 stream
+    pub fn spawn(cfg: &NatsConfig) -> Result<(Self, JoinHandle<()>)> {
+        let metrics = Arc::new(SinkMetrics::new("nats"));
+        let (tx, rx) = mpsc::channel(cfg.buffer_size);
+        let writer = Writer {
+            config: cfg.clone(),
+            client: None,
+            context: None,
+            metrics: metrics.clone(),
+        };
+        let task = tokio::spawn(write_loop(writer, rx, metrics.clone()));
+        let sink = Self {
+            tx,
+            prefix: cfg.subject_prefix.clone(),
+            metrics,
+        };
+        Ok((sink, task))
+    }
+
+    // 缓冲满时丢弃
+    fn send(&self, message: Message) {
+        if self.tx.try_send(message).is_err() {
+            SinkMetrics::incr(&self.metrics.dropped);
+        }
+    }
+}
+
+impl EventSink for NatsSink {
+    fn send_event(&self, event: &Arc<Event>, cfg: &AppConfig) {
+        let nats_sink = &cfg.sinks.nats;
+        if event.priority < nats_sink.min_priority {
+            return;
+        }
+        let payload = match nats_sink.encoding.encode(event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("failed to encode event: {:?}", e);
+                return;
+            }
+        };
+        let symbol = if event.symbol.is_empty() {
+            "market"
+        } else {
+            &event.symbol
+        };
+        self.send(Message {
+            subject: format!("{}.events.{:?}.{}", self.prefix, event.event_type(), symbol),
+            id: event.id.clone(),
+            encoding: nats_sink.encoding,
+            payload,
+        });
+    }
+
+    fn send_kline(&self, bar: &ClosedBar, cfg: &AppConfig) {
+        if !cfg.sinks.nats.klines {
+            return;
+        }
+        let payload = match serde_json::to_vec(bar) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("failed to encode kline: {:?}", e);
+                return;
+            }
+        };
+        self.send(Message {
+            subject: format!("{}.klines.{}.{}", self.prefix, bar.symbol, bar.interval),
+            id: format!("{}.{}.{}", bar.symbol, bar.interval, bar.kline.start_ts),
+            encoding: Encoding::Json,
+            payload,
+        });
+    }
+
+    fn metrics(&self) -> Arc<SinkMetrics> {
+        self.metrics.clone()
+    }
+}
+
+struct Writer {
+    config: NatsConfig,
+    client: Option<Client>,
+    context: Option<jetstream::Context>,
+    metrics: Arc<SinkMetrics>,
+}
+
+impl Writer {
+    async fn connect(&mut self) -> Result<()> {
+        let config = &self.config;
+        let mut options = ConnectOptions::new()
+            .name("perpx")
+            .connection_timeout(CONNECT_TIMEOUT);
+        if let Some(token) = &config.token {
+            options = options.token(token.clone());
+        }
+        if let Some(path) = &config.credentials_file {
+            options = options.credentials_file(path).await?;
+        }
+        let urls: Vec<&str> = config.url.split(',').map(str::trim).collect();
+        let client = options.connect(urls.as_slice()).await?;
+        let context = jetstream::new(client.clone());
+        // 已存在的 stream 不会修改配置
+        if config.create_stream {
+            context
+                .get_or_create_stream(stream::Config {
+                    name: config.stream.clone(),
+                    subjects: vec![format!("{}.>", config.subject_prefix)],
+                    max_age: Duration::from_secs(config.max_age_hours * 3600),
+                    ..Default::default()
+                })
+                .await?;
+        } else {
+            context.get_stream(&config.stream).await?;
+        }
+        info!("connected to nats, publishing to stream {}", config.stream);
+        self.client = Some(client);
+        self.context = Some(context);
+        Ok(())
+    }
+
+    // 断线后重新连接，stream 被删除时会重新创建
+    fn disconnect(&mut self) {
+        self.client = None;
+        self.context = None;
+    }
+
+    // 先发布整批再等待确认，确认成功和被拒绝的消息从 batch 中移除
+    async fn write(&mut self, batch: &mut Vec<Message>) -> Result<()> {
+        if self.context.is_none() {
+            self.connect().await?;
+        }
+        // 超过服务端大小限制的消息会导致连接被关闭，发布前丢弃
+        let max_payload = self.client.as_ref().unwrap().server_info().max_payload;
+        batch.retain(|message| {
+            if message.payload.len() <= max_payload {
+                return true;
+            }
+            error!(
+                "dropping nats message {} on {}: {} bytes exceeds max payload {}",
+                message.id,
+                message.subject,
+                message.payload.len(),
+                max_payload
+            );
+            SinkMetrics::incr(&self.metrics.dropped);
+            false
+        });
+        let context = self.context.as_ref().unwrap();
+        let mut acks = Vec::with_capacity(batch.len());
+        for message in batch.iter() {
+            let mut headers = HeaderMap::new();
+            headers.insert(ENCODING_HEADER, message.encoding.name());
+            let publish = Publish::build()
+                .payload(message.payload.clone().into())
+                .headers(headers)
+                .message_id(&message.id);
+            acks.push(
+                context
+                    .send_publish(message.subject.clone(), publish)
+                    .await?,
+            );
+        }
+        let mut results = join_all(acks.into_iter().map(IntoFuture::into_future))
+            .await
+            .into_iter();
+        let mut error = None;
+        batch.retain(|message| match results.next() {
+            Some(Err(e)) if is_rejected(&e) => {
+                error!(
+                    "nats rejected message {} on {}: {:?}",
+                    message.id, message.subject, e
+                );
+                SinkMetrics::incr(&self.metrics.dropped);
+                false
+            }
+            Some(Err(e)) => {
+                error = Some(e);
+                true
+            }
+            _ => {
+                SinkMetrics::incr(&self.metrics.written);
+                false
+            }
+        });
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+// JetStream 返回了错误应答，而不是超时或连接断开
+fn is_rejected(e: &PublishError) -> bool {
+    match e.kind() {
+        PublishErrorKind::WrongLastMessageId | PublishErrorKind::WrongLastSequence => true,
+        PublishErrorKind::Other => {
+            std::error::Error::source(e).is_some_and(|s| s.is::<jetstream::Error>())
+        }
+        PublishErrorKind::StreamNotFound
+        | PublishErrorKind::TimedOut
+        | PublishErrorKind::BrokenPipe => false,
+    }
+}
+
+async fn write_loop(
+    mut writer: Writer,
+    mut rx: mpsc::Receiver<Message>,
+    metrics: Arc<SinkMetrics>,
+) {
+    let mut backoff = MIN_BACKOFF;
+    let mut failing_since: Option<Instant> = None;
+
+    while let Some(first) = rx.recv().await {
+        let mut batch = vec![first];
+        while batch.len() < BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(message) => batch.push(message),
+                Err(_) => break,
+            }
+        }
+
+        // 发布失败时保留未确认的消息重试，新消息留在通道中
+        loop {
+            metrics.buffered.store(rx.len() as u64, Ordering::Relaxed);
+            let result = tokio::time::timeout(WRITE_TIMEOUT, writer.write(&mut batch))
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("timed out after {:?}", WRITE_TIMEOUT)));
+            match result {
+                Ok(()) => {
+                    if failing_since.take().is_some() {
+                        info!("nats recovered, {} messages buffered", rx.len());
+                    }
+                    backoff = MIN_BACKOFF;
+                    break;
+                }
+                Err(e) => {
+                    SinkMetrics::incr(&metrics.failures);
+                    writer.disconnect();
+                    let since = *failing_since.get_or_insert_with(|| {
+                        error!("nats unavailable, buffering messages: {:?}", e);
+                        Instant::now()
+                    });
+                    if rx.is_closed() && since.elapsed() >= DRAIN_TIMEOUT {
+                        let count = batch.len() + rx.len();
+                        error!(
+                            "nats still unavailable, giving up {} buffered messages",
+                            count
+                        );
+                        metrics.dropped.fetch_add(count as u64, Ordering::Relaxed);
+                        return;
+                    }
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+    metrics.buffered.store(0, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn rejected_errors() {
+        assert!(is_rejected(&PublishErrorKind::WrongLastSequence.into()));
+        assert!(!is_rejected(&PublishErrorKind::TimedOut.into()));
+        assert!(!is_rejected(&PublishErrorKind::BrokenPipe.into()));
+        assert!(!is_rejected(&PublishErrorKind::StreamNotFound.into()));
+        assert!(!is_rejected(&PublishErrorKind::Other.into()));
+    }
+
+    fn message(prefix: &str, id: &str, size: usize) -> Message {
+        Message {
+            subject: format!("{}.events.test", prefix),
+            id: id.to_string(),
+            encoding: Encoding::Json,
+            payload: vec![b'x'; size],
+        }
+    }
+
+    // 需要开启 JetStream 的 nats，地址通过 PERPX_TEST_NATS_URL 指定：cargo test -- --ignored
+    #[tokio::test]
+    #[ignore]
+    async fn publish_dedup_and_reject() {
+        let url = std::env::var("PERPX_TEST_NATS_URL")
+            .unwrap_or_else(|_| "nats://127.0.0.1:4222".to_string());
+        let name = format!("PERPX_TEST_{}", Uuid::new_v4().simple());
+        let prefix = name.to_lowercase();
+        // 预先创建限制消息大小的 stream
+        let context = jetstream::new(async_nats::connect(&url).await.unwrap());
+        context
+            .create_stream(stream::Config {
+                name: name.clone(),
+                subjects: vec![format!("{}.>", prefix)],
+                max_message_size: 1024,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let config: NatsConfig = toml::from_str(&format!(
+            "url = {:?}\nstream = {:?}\nsubject_prefix = {:?}\ncreate_stream = false",
+            url, name, prefix
+        ))
+        .unwrap();
+        let metrics = Arc::new(SinkMetrics::new("nats"));
+        let mut writer = Writer {
+            config,
+            client: None,
+            context: None,
+            metrics: metrics.clone(),
+        };
+        // 重复的 id 由 JetStream 去重，超过 stream 大小限制的消息被拒绝后丢弃
+        let mut batch = vec![
+            message(&prefix, "a", 10),
+            message(&prefix, "a", 10),
+            message(&prefix, "b", 2048),
+            message(&prefix, "c", 10),
+        ];
+        writer.write(&mut batch).await.unwrap();
+        assert!(batch.is_empty());
+        assert_eq!(metrics.written.load(Ordering::Relaxed), 3);
+        assert_eq!(metrics.dropped.load(Ordering::Relaxed), 1);
+
+        let mut stream = context.get_stream(&name).await.unwrap();
+        assert_eq!(stream.info().await.unwrap().state.messages, 2);
+        context.delete_stream(&name).await.unwrap();
+    }
+}
@@ -0,0 +1,425 @@
+use crate::config::{Config as AppConfig, PostgresConfig};
+use crate::metrics::SinkMetrics;
+use crate::sink::EventSink;
+use crate::types::{ClosedBar, Event, Interval};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_postgres::{Client, NoTls};
+use tracing::{error, info};
+
+// ========== PostgreSQL / TimescaleDB ==========
+// 长期保存事件和收盘k线，用于历史分析
+// 表结构由 migrations/ 下的脚本维护，连接后自动执行未应用的脚本，记录在 schema_migrations
+// postgres 不可用时在内存中缓冲 buffer_size 条记录，超过后丢弃新记录
+
+// 按版本顺序执行，已发布的脚本不要修改，变更表结构时新增脚本
+const MIGRATIONS: [(i32, &str, &str); 1] =
+    [(1, "init", include_str!("../migrations/0001_init.sql"))];
+// 多个实例同时启动时只有一个执行迁移
+const MIGRATION_LOCK: i64 = 0x0070_6572_7078; // "perpx"
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// 退出时最多等待多久把缓冲写完
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+const INSERT_EVENTS: &str = "
+INSERT INTO events (id, ts, symbol, period, event_type, priority, severity, suppressed, schema_version, value)
+SELECT id::uuid, 'epoch'::timestamptz + ts * interval '1 millisecond', symbol, period, event_type,
+       priority, severity, suppressed, schema_version, value::jsonb
+FROM UNNEST($1::text[], $2::int8[], $3::text[], $4::text[], $5::text[], $6::text[], $7::float8[],
+            $8::int8[], $9::int4[], $10::text[])
+     AS t(id, ts, symbol, period, event_type, priority, severity, suppressed, schema_version, value)
+ON CONFLICT DO NOTHING";
+
+const UPSERT_KLINES: &str = "
+INSERT INTO klines (symbol, interval, start_ts, open, high, low, close, volume)
+SELECT symbol, interval, 'epoch'::timestamptz + start_ts * interval '1 millisecond',
+       open, high, low, close, volume
+FROM UNNEST($1::text[], $2::text[], $3::int8[], $4::float8[], $5::float8[], $6::float8[],
+            $7::float8[], $8::float8[])
+     AS t(symbol, interval, start_ts, open, high, low, close, volume)
+ON CONFLICT (symbol, interval, start_ts) DO UPDATE SET
+    open = EXCLUDED.open, high = EXCLUDED.high, low = EXCLUDED.low,
+    close = EXCLUDED.close, volume = EXCLUDED.volume";
+
+enum Record {
+    Event(Arc<Event>),
+    Kline(ClosedBar),
+}
+
+#[derive(Clone)]
+pub struct PostgresSink {
+    tx: mpsc::Sender<Record>,
+    metrics: Arc<SinkMetrics>,
+}
+
+impl PostgresSink {
+    /// 启动写入任务，第一次写入时才连接 postgres 并执行迁移
+    pub fn spawn(cfg: &PostgresConfig) -> Result<(Self, JoinHandle<()>)> {
+        let config = cfg
+            .url
+            .parse::<tokio_postgres::Config>()
+            .context("invalid postgres.url")?;
+        let metrics = Arc::new(SinkMetrics::new("postgres"));
+        let (tx, rx) = mpsc::channel(cfg.buffer_size);
+        let writer = Writer {
+            config,
+            tls: cfg.tls,
+            client: None,
+        };
+        let task = tokio::spawn(write_loop(
+            writer,
+            cfg.batch_size,
+            Duration::from_millis(cfg.batch_window_ms),
+            rx,
+            metrics.clone(),
+        ));
+        Ok((Self { tx, metrics }, task))
+    }
+
+    // 缓冲满时丢弃
+    fn send(&self, record: Record) {
+        if self.tx.try_send(record).is_err() {
+            SinkMetrics::incr(&self.metrics.dropped);
+        }
+    }
+}
+
+impl EventSink for PostgresSink {
+    fn send_event(&self, event: &Arc<Event>, cfg: &AppConfig) {
+        if event.priority >= cfg.sinks.postgres.min_priority {
+            self.send(Record::Event(event.clone()));
+        }
+    }
+
+    fn send_kline(&self, bar: &ClosedBar, cfg: &AppConfig) {
+        if cfg.sinks.postgres.klines {
+            self.send(Record::Kline(bar.clone()));
+        }
+    }
+
+    fn metrics(&self) -> Arc<SinkMetrics> {
+        self.metrics.clone()
+    }
+}
+
+struct Writer {
+    config: tokio_postgres::Config,
+    tls: bool,
+    client: Option<Client>,
+}
+
+impl Writer {
+    async fn connect(&self) -> Result<Client> {
+        let mut client = if self.tls {
+            let roots =
+                rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            let tls_config = rustls::ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let tls = tokio_postgres_rustls::MakeRustlsConnect::new(tls_config);
+            let (client, connection) = self.config.connect(tls).await?;
+            tokio::spawn(connection);
+            client
+        } else {
+            let (client, connection) = self.config.connect(NoTls).await?;
+            tokio::spawn(connection);
+            client
+        };
+        migrate(&mut client).await?;
+        Ok(client)
+    }
+
+    // 同一批的事件和k线在一个事务中写入
+    async fn write(&mut self, batch: &[Record]) -> Result<()> {
+        if self.client.as_ref().is_none_or(Client::is_closed) {
+            self.client = Some(self.connect().await?);
+        }
+        let client = self.client.as_mut().unwrap();
+        let transaction = client.transaction().await?;
+
+        let events: Vec<&Event> = batch
+            .iter()
+            .filter_map(|r| match r {
+                Record::Event(event) => Some(event.as_ref()),
+                Record::Kline(_) => None,
+            })
+            .collect();
+        if !events.is_empty() {
+            let values: Vec<String> = events.iter().map(|e| e.value_json()).collect();
+            let ids: Vec<&str> = events.iter().map(|e| e.id.as_str()).collect();
+            let ts: Vec<i64> = events.iter().map(|e| e.timestamp as i64).collect();
+            let symbols: Vec<&str> = events.iter().map(|e| e.symbol.as_str()).collect();
+            let periods: Vec<&str> = events.iter().map(|e| e.period.as_str()).collect();
+            let event_types: Vec<String> = events
+                .iter()
+                .map(|e| format!("{:?}", e.event_type()))
+                .collect();
+            let priorities: Vec<String> = events
+                .iter()
+                .map(|e| format!("{:?}", e.priority).to_lowercase())
+                .collect();
+            let severities: Vec<f64> = events.iter().map(|e| e.severity).collect();
+            let suppressed: Vec<i64> = events.iter().map(|e| e.suppressed as i64).collect();
+            let versions: Vec<i32> = events.iter().map(|e| e.schema_version as i32).collect();
+            transaction
+                .execute(
+                    INSERT_EVENTS,
+                    &[
+                        &ids,
+                        &ts,
+                        &symbols,
+                        &periods,
+                        &event_types,
+                        &priorities,
+                        &severities,
+                        &suppressed,
+                        &versions,
+                        &values,
+                    ],
+                )
+                .await?;
+        }
+
+        // 同一批中重复的k线只保留最后一次，否则 ON CONFLICT 会报错
+        let mut klines: HashMap<(&str, Interval, u64), &ClosedBar> = HashMap::new();
+        for record in batch {
+            if let Record::Kline(bar) = record {
+                let key = (bar.symbol.as_str(), bar.interval, bar.kline.start_ts);
+                klines.insert(key, bar);
+            }
+        }
+        if !klines.is_empty() {
+            let bars: Vec<&ClosedBar> = klines.into_values().collect();
+            let symbols: Vec<&str> = bars.iter().map(|b| b.symbol.as_str()).collect();
+            let intervals: Vec<String> = bars.iter().map(|b| b.interval.to_string()).collect();
+            let start_ts: Vec<i64> = bars.iter().map(|b| b.kline.start_ts as i64).collect();
+            let open: Vec<f64> = bars.iter().map(|b| b.kline.open).collect();
+            let high: Vec<f64> = bars.iter().map(|b| b.kline.high).collect();
+            let low: Vec<f64> = bars.iter().map(|b| b.kline.low).collect();
+            let close: Vec<f64> = bars.iter().map(|b| b.kline.close).collect();
+            let volume: Vec<f64> = bars.iter().map(|b| b.kline.volume).collect();
+            transaction
+                .execute(
+                    UPSERT_KLINES,
+                    &[
+                        &symbols, &intervals, &start_ts, &open, &high, &low, &close, &volume,
+                    ],
+                )
+                .await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+}
+
+/// 执行未应用的迁移脚本
+async fn migrate(client: &mut Client) -> Result<()> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])
+        .await?;
+    transaction
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version integer PRIMARY KEY,
+                name text NOT NULL,
+                applied_at timestamptz NOT NULL DEFAULT now()
+            )",
+        )
+        .await?;
+    let applied: Vec<i32> = transaction
+        .query("SELECT version FROM schema_migrations", &[])
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    for (version, name, sql) in MIGRATIONS {
+        if applied.contains(&version) {
+            continue;
+        }
+        transaction
+            .batch_execute(sql)
+            .await
+            .with_context(|| format!("migration {} {} failed", version, name))?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+                &[&version, &name],
+            )
+            .await?;
+        info!("applied postgres migration {} {}", version, name);
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn write_loop(
+    mut writer: Writer,
+    batch_size: usize,
+    batch_window: Duration,
+    mut rx: mpsc::Receiver<Record>,
+    metrics: Arc<SinkMetrics>,
+) {
+    let mut backoff = MIN_BACKOFF;
+    let mut failing_since: Option<Instant> = None;
+
+    while let Some(first) = rx.recv().await {
+        // 等待一个窗口合并成一批写出
+        let mut batch = vec![first];
+        let window = tokio::time::sleep(batch_window);
+        tokio::pin!(window);
+        while batch.len() < batch_size {
+            tokio::select! {
+                _ = &mut window => break,
+                record = rx.recv() => match record {
+                    Some(record) => batch.push(record),
+                    None => break,
+                },
+            }
+        }
+
+        // 写入失败时保留这一批重试，新记录留在通道中
+        loop {
+            metrics.buffered.store(rx.len() as u64, Ordering::Relaxed);
+            match writer.write(&batch).await {
+                Ok(()) => {
+                    metrics
+                        .written
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    if failing_since.take().is_some() {
+                        info!("postgres recovered, {} records buffered", rx.len());
+                    }
+                    backoff = MIN_BACKOFF;
+                    break;
+                }
+                Err(e) => {
+                    SinkMetrics::incr(&metrics.failures);
+                    // SQL 执行出错（而不是连接问题）时重试也不会成功，丢弃这一批
+                    if writer.client.as_ref().is_some_and(|c| !c.is_closed())
+                        && e.downcast_ref::<tokio_postgres::Error>()
+                            .and_then(|e| e.code())
+                            .is_some()
+                    {
+                        error!(
+                            "failed to write {} records to postgres: {:?}",
+                            batch.len(),
+                            e
+                        );
+                        metrics
+                            .dropped
+                            .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                        break;
+                    }
+                    let since = *failing_since.get_or_insert_with(|| {
+                        error!("postgres unavailable, buffering records: {:?}", e);
+                        Instant::now()
+                    });
+                    if rx.is_closed() && since.elapsed() >= DRAIN_TIMEOUT {
+                        let count = batch.len() + rx.len();
+                        error!(
+                            "postgres still unavailable, giving up {} buffered records",
+                            count
+                        );
+                        metrics.dropped.fetch_add(count as u64, Ordering::Relaxed);
+                        return;
+                    }
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+    metrics.buffered.store(0, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Kline;
+    use uuid::Uuid;
+
+    fn bar(close: f64) -> ClosedBar {
+        let mut kline = Kline::new(1_704_067_200_000, 100.0, 1.0);
+        kline.update(close, 1.0);
+        ClosedBar {
+            symbol: "BTCUSDT".into(),
+            interval: Interval::Min5,
+            kline,
+        }
+    }
+
+    // 需要 postgres，地址通过 PERPX_TEST_POSTGRES_URL 指定：cargo test -- --ignored
+    // 在临时 schema 中执行迁移和写入，结束后删除
+    #[tokio::test]
+    #[ignore]
+    async fn migrate_and_upsert() {
+        let url = std::env::var("PERPX_TEST_POSTGRES_URL")
+            .unwrap_or_else(|_| "postgres://postgres@localhost/perpx".to_string());
+        let schema = format!("perpx_test_{}", Uuid::new_v4().simple());
+        let mut config: tokio_postgres::Config = url.parse().unwrap();
+        let (admin, connection) = config.connect(NoTls).await.unwrap();
+        tokio::spawn(connection);
+        admin
+            .batch_execute(&format!("CREATE SCHEMA {}", schema))
+            .await
+            .unwrap();
+        config.options(format!("-c search_path={}", schema));
+
+        let event = Arc::new(Event::fixture("BTCUSDT", 1_704_067_200_000));
+        for close in [101.0, 102.0] {
+            // 每次重新连接，迁移只执行一次
+            let mut writer = Writer {
+                config: config.clone(),
+                tls: false,
+                client: None,
+            };
+            let batch = [
+                Record::Event(event.clone()),
+                Record::Kline(bar(close - 0.5)),
+                Record::Kline(bar(close)),
+            ];
+            writer.write(&batch).await.unwrap();
+        }
+
+        let client = Writer {
+            config,
+            tls: false,
+            client: None,
+        }
+        .connect()
+        .await
+        .unwrap();
+        let count = |table: &'static str| {
+            let client = &client;
+            async move {
+                let row = client
+                    .query_one(&format!("SELECT count(*) FROM {}", table), &[])
+                    .await
+                    .unwrap();
+                row.get::<_, i64>(0)
+            }
+        };
+        assert_eq!(count("schema_migrations").await, MIGRATIONS.len() as i64);
+        assert_eq!(count("events").await, 1);
+        assert_eq!(count("klines").await, 1);
+        let row = client
+            .query_one("SELECT close, value->>'count' FROM klines, events", &[])
+            .await
+            .unwrap();
+        assert_eq!(row.get::<_, f64>(0), 102.0);
+        assert_eq!(row.get::<_, &str>(1), "3");
+
+        admin
+            .batch_execute(&format!("DROP SCHEMA {} CASCADE", schema))
+            .await
+            .unwrap();
+    }
+}
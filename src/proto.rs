@@ -0,0 +1,393 @@
+// proto/perpx.proto 对应的 prost 结构，修改时需要和 .proto 文件保持一致
+use crate::types;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Priority {
+    Low = 0,
+    Normal = 1,
+    High = 2,
+    Critical = 3,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Event {
+    #[prost(uint32, tag = "1")]
+    pub schema_version: u32,
+    #[prost(string, tag = "2")]
+    pub id: String,
+    #[prost(string, tag = "3")]
+    pub symbol: String,
+    #[prost(string, tag = "4")]
+    pub period: String,
+    #[prost(string, tag = "5")]
+    pub event_type: String,
+    #[prost(uint64, tag = "6")]
+    pub timestamp: u64,
+    #[prost(double, tag = "7")]
+    pub severity: f64,
+    #[prost(enumeration = "Priority", tag = "8")]
+    pub priority: i32,
+    #[prost(uint64, tag = "9")]
+    pub suppressed: u64,
+    #[prost(
+        oneof = "event::Value",
+        tags = "20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30"
+    )]
+    pub value: Option<event::Value>,
+}
+
+pub mod event {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(message, tag = "20")]
+        ConsecutiveMove(super::ConsecutiveMove),
+        #[prost(message, tag = "21")]
+        VolatilitySpike(super::VolatilitySpike),
+        #[prost(message, tag = "22")]
+        FundingRate(super::FundingRate),
+        #[prost(message, tag = "23")]
+        FundingCountdown(super::FundingCountdown),
+        #[prost(message, tag = "24")]
+        FundingIntervalChange(super::FundingIntervalChange),
+        #[prost(message, tag = "25")]
+        PremiumDeviation(super::PremiumDeviation),
+        #[prost(message, tag = "26")]
+        MarketRanking(super::MarketRanking),
+        #[prost(message, tag = "27")]
+        MarketBreadth(super::MarketBreadth),
+        #[prost(message, tag = "28")]
+        BtcDivergence(super::BtcDivergence),
+        #[prost(message, tag = "29")]
+        NewListing(super::ListingChange),
+        #[prost(message, tag = "30")]
+        Delisting(super::ListingChange),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ConsecutiveMove {
+    #[prost(uint32, tag = "1")]
+    pub count: u32,
+    #[prost(double, tag = "2")]
+    pub turnover: f64,
+    #[prost(sint32, tag = "3")]
+    pub direction: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VolatilitySpike {
+    #[prost(double, tag = "1")]
+    pub amplitude: f64,
+    #[prost(double, tag = "2")]
+    pub avg_amplitude: f64,
+    #[prost(double, tag = "3")]
+    pub volume: f64,
+    #[prost(double, tag = "4")]
+    pub turnover: f64,
+    #[prost(sint32, tag = "5")]
+    pub direction: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FundingRate {
+    #[prost(double, tag = "1")]
+    pub funding_rate: f64,
+    #[prost(uint64, tag = "2")]
+    pub next_funding_time: u64,
+    #[prost(double, tag = "3")]
+    pub funding_interval_hours: f64,
+    #[prost(double, tag = "4")]
+    pub annualized_rate: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FundingCountdown {
+    #[prost(double, tag = "1")]
+    pub funding_rate: f64,
+    #[prost(uint64, tag = "2")]
+    pub next_funding_time: u64,
+    #[prost(uint64, tag = "3")]
+    pub minutes_before: u64,
+    #[prost(double, tag = "4")]
+    pub funding_interval_hours: f64,
+    #[prost(double, tag = "5")]
+    pub annualized_rate: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FundingIntervalChange {
+    #[prost(double, tag = "1")]
+    pub old_interval_hours: f64,
+    #[prost(double, tag = "2")]
+    pub new_interval_hours: f64,
+    #[prost(uint64, tag = "3")]
+    pub next_funding_time: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PremiumDeviation {
+    #[prost(string, tag = "1")]
+    pub reason: String,
+    #[prost(double, tag = "2")]
+    pub mark_price: f64,
+    #[prost(double, tag = "3")]
+    pub index_price: f64,
+    #[prost(double, optional, tag = "4")]
+    pub last_price: Option<f64>,
+    #[prost(double, tag = "5")]
+    pub premium: f64,
+    #[prost(double, optional, tag = "6")]
+    pub avg_premium: Option<f64>,
+    #[prost(double, optional, tag = "7")]
+    pub basis: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RankEntry {
+    #[prost(string, tag = "1")]
+    pub symbol: String,
+    #[prost(double, tag = "2")]
+    pub price: f64,
+    #[prost(double, tag = "3")]
+    pub value: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RankEntryList {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<RankEntry>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RankChange {
+    #[prost(string, tag = "1")]
+    pub symbol: String,
+    #[prost(uint64, tag = "2")]
+    pub rank: u64,
+    #[prost(uint64, tag = "3")]
+    pub prev_rank: u64,
+    #[prost(sint64, tag = "4")]
+    pub change: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MarketRanking {
+    #[prost(uint64, tag = "1")]
+    pub symbol_count: u64,
+    #[prost(map = "string, message", tag = "2")]
+    pub gainers: HashMap<String, RankEntryList>,
+    #[prost(map = "string, message", tag = "3")]
+    pub losers: HashMap<String, RankEntryList>,
+    #[prost(message, repeated, tag = "4")]
+    pub top_turnover: Vec<RankEntry>,
+    #[prost(message, repeated, tag = "5")]
+    pub turnover_rank_changes: Vec<RankChange>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum BreadthState {
+    Neutral = 0,
+    Bullish = 1,
+    Bearish = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MarketBreadth {
+    #[prost(enumeration = "BreadthState", tag = "1")]
+    pub state: i32,
+    #[prost(uint64, tag = "2")]
+    pub symbol_count: u64,
+    #[prost(uint64, tag = "3")]
+    pub up_count: u64,
+    #[prost(uint64, tag = "4")]
+    pub down_count: u64,
+    #[prost(double, tag = "5")]
+    pub up_ratio: f64,
+    #[prost(double, tag = "6")]
+    pub down_ratio: f64,
+    #[prost(double, tag = "7")]
+    pub avg_return: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BtcDivergence {
+    #[prost(double, tag = "1")]
+    pub symbol_return: f64,
+    #[prost(double, tag = "2")]
+    pub btc_return: f64,
+    #[prost(double, tag = "3")]
+    pub beta: f64,
+    #[prost(double, tag = "4")]
+    pub residual: f64,
+    #[prost(double, tag = "5")]
+    pub z_score: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListingChange {
+    #[prost(string, tag = "1")]
+    pub reason: String,
+    #[prost(string, optional, tag = "2")]
+    pub status: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub prev_status: Option<String>,
+    #[prost(uint64, optional, tag = "4")]
+    pub first_seen: Option<u64>,
+    #[prost(uint64, optional, tag = "5")]
+    pub last_seen: Option<u64>,
+    #[prost(uint64, optional, tag = "6")]
+    pub onboard_date: Option<u64>,
+}
+
+// ========== 类型转换 ==========
+impl From<&types::Event> for Event {
+    fn from(e: &types::Event) -> Self {
+        let priority = match e.priority {
+            types::Priority::Low => Priority::Low,
+            types::Priority::Normal => Priority::Normal,
+            types::Priority::High => Priority::High,
+            types::Priority::Critical => Priority::Critical,
+        };
+        Self {
+            schema_version: e.schema_version,
+            id: e.id.clone(),
+            symbol: e.symbol.clone(),
+            period: e.period.clone(),
+            event_type: format!("{:?}", e.event_type()),
+            timestamp: e.timestamp,
+            severity: e.severity,
+            priority: priority as i32,
+            suppressed: e.suppressed,
+            value: Some(event::Value::from(&e.payload)),
+        }
+    }
+}
+
+impl From<&types::EventPayload> for event::Value {
+    fn from(payload: &types::EventPayload) -> Self {
+        use types::EventPayload as P;
+        match payload {
+            P::ConsecutiveMove(v) => event::Value::ConsecutiveMove(ConsecutiveMove {
+                count: v.count,
+                turnover: v.turnover,
+                direction: v.direction as i32,
+            }),
+            P::VolatilitySpike(v) => event::Value::VolatilitySpike(VolatilitySpike {
+                amplitude: v.amplitude,
+                avg_amplitude: v.avg_amplitude,
+                volume: v.volume,
+                turnover: v.turnover,
+                direction: v.direction as i32,
+            }),
+            P::FundingRate(v) => event::Value::FundingRate(FundingRate {
+                funding_rate: v.funding_rate,
+                next_funding_time: v.next_funding_time,
+                funding_interval_hours: v.funding_interval_hours,
+                annualized_rate: v.annualized_rate,
+            }),
+            P::FundingCountdown(v) => event::Value::FundingCountdown(FundingCountdown {
+                funding_rate: v.funding_rate,
+                next_funding_time: v.next_funding_time,
+                minutes_before: v.minutes_before,
+                funding_interval_hours: v.funding_interval_hours,
+                annualized_rate: v.annualized_rate,
+            }),
+            P::FundingIntervalChange(v) => {
+                event::Value::FundingIntervalChange(FundingIntervalChange {
+                    old_interval_hours: v.old_interval_hours,
+                    new_interval_hours: v.new_interval_hours,
+                    next_funding_time: v.next_funding_time,
+                })
+            }
+            P::PremiumDeviation(v) => event::Value::PremiumDeviation(PremiumDeviation {
+                reason: v.reason.clone(),
+                mark_price: v.mark_price,
+                index_price: v.index_price,
+                last_price: v.last_price,
+                premium: v.premium,
+                avg_premium: v.avg_premium,
+                basis: v.basis,
+            }),
+            P::MarketRanking(v) => event::Value::MarketRanking(MarketRanking {
+                symbol_count: v.symbol_count as u64,
+                gainers: rank_lists(&v.gainers),
+                losers: rank_lists(&v.losers),
+                top_turnover: v.top_turnover.iter().map(RankEntry::from).collect(),
+                turnover_rank_changes: v
+                    .turnover_rank_changes
+                    .iter()
+                    .map(|c| RankChange {
+                        symbol: c.symbol.clone(),
+                        rank: c.rank as u64,
+                        prev_rank: c.prev_rank as u64,
+                        change: c.change,
+                    })
+                    .collect(),
+            }),
+            P::MarketBreadth(v) => event::Value::MarketBreadth(MarketBreadth {
+                state: match v.state {
+                    types::BreadthState::Neutral => BreadthState::Neutral,
+                    types::BreadthState::Bullish => BreadthState::Bullish,
+                    types::BreadthState::Bearish => BreadthState::Bearish,
+                } as i32,
+                symbol_count: v.symbol_count as u64,
+                up_count: v.up_count as u64,
+                down_count: v.down_count as u64,
+                up_ratio: v.up_ratio,
+                down_ratio: v.down_ratio,
+                avg_return: v.avg_return,
+            }),
+            P::BtcDivergence(v) => event::Value::BtcDivergence(BtcDivergence {
+                symbol_return: v.symbol_return,
+                btc_return: v.btc_return,
+                beta: v.beta,
+                residual: v.residual,
+                z_score: v.z_score,
+            }),
+            P::NewListing(v) => event::Value::NewListing(ListingChange::from(v)),
+            P::Delisting(v) => event::Value::Delisting(ListingChange::from(v)),
+        }
+    }
+}
+
+impl From<&types::RankEntry> for RankEntry {
+    fn from(e: &types::RankEntry) -> Self {
+        Self {
+            symbol: e.symbol.clone(),
+            price: e.price,
+            value: e.value,
+        }
+    }
+}
+
+impl From<&types::ListingChange> for ListingChange {
+    fn from(c: &types::ListingChange) -> Self {
+        Self {
+            reason: c.reason.clone(),
+            status: c.status.clone(),
+            prev_status: c.prev_status.clone(),
+            first_seen: c.first_seen,
+            last_seen: c.last_seen,
+            onboard_date: c.onboard_date,
+        }
+    }
+}
+
+fn rank_lists(
+    lists: &HashMap<types::Interval, Vec<types::RankEntry>>,
+) -> HashMap<String, RankEntryList> {
+    lists
+        .iter()
+        .map(|(interval, entries)| {
+            (
+                interval.to_string(),
+                RankEntryList {
+                    entries: entries.iter().map(RankEntry::from).collect(),
+                },
+            )
+        })
+        .collect()
+}
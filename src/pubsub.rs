@@ -0,0 +1,204 @@
+use crate::config::Config as AppConfig;
+use crate::metrics::SinkMetrics;
+use crate::redis::{client_config, LazyClient};
+use crate::sink::EventSink;
+use crate::types::Event;
+use anyhow::Result;
+use rustis::resp::cmd;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+// ========== redis pub/sub ==========
+// 即发即弃：redis 不可用或没有订阅者时消息直接丢弃，不缓冲，适合只关心实时性的订阅方
+
+const PLACEHOLDERS: [&str; 5] = ["namespace", "type", "symbol", "period", "priority"];
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+// 每次 pipeline 最多发布的事件数
+const BATCH_SIZE: usize = 200;
+
+/// 校验频道模板，只能使用 {namespace} {type} {symbol} {period} {priority}
+pub fn check_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed '{{' in {:?}", template))?;
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder {{{}}}, expected one of {:?}",
+                name, PLACEHOLDERS
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// 生成频道名，模板中有 {symbol} 而事件没有交易对（全市场事件）时返回 None
+pub fn channel_name(template: &str, namespace: &str, event: &Event) -> Option<String> {
+    if template.contains("{symbol}") && event.symbol.is_empty() {
+        return None;
+    }
+    Some(
+        template
+            .replace("{namespace}", namespace)
+            .replace("{type}", &format!("{:?}", event.event_type()))
+            .replace("{symbol}", &event.symbol)
+            .replace("{period}", &event.period)
+            .replace(
+                "{priority}",
+                &format!("{:?}", event.priority).to_lowercase(),
+            ),
+    )
+}
+
+// 一个事件发布到的所有频道
+struct Publication {
+    channels: Vec<String>,
+    message: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct PubSubSink {
+    tx: mpsc::Sender<Publication>,
+    metrics: Arc<SinkMetrics>,
+}
+
+impl PubSubSink {
+    /// 启动发布任务，第一次发布时才连接 redis
+    pub fn spawn(cfg: &AppConfig) -> Result<(Self, JoinHandle<()>)> {
+        let mut config = client_config(&cfg.redis)?;
+        config.command_timeout = COMMAND_TIMEOUT;
+        let metrics = Arc::new(SinkMetrics::new("redis_pubsub"));
+        let (tx, rx) = mpsc::channel(10000);
+        let client = LazyClient::new("redis pub/sub", config);
+        let task = tokio::spawn(publish_loop(client, rx, metrics.clone()));
+        Ok((Self { tx, metrics }, task))
+    }
+
+    /// 交给发布任务，任务跟不上时丢弃
+    fn send(&self, publication: Publication) {
+        let count = publication.channels.len() as u64;
+        if self.tx.try_send(publication).is_err() {
+            self.metrics.dropped.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+}
+
+impl EventSink for PubSubSink {
+    fn send_event(&self, event: &Arc<Event>, cfg: &AppConfig) {
+        let pubsub_sink = &cfg.sinks.pubsub;
+        if !pubsub_sink.enabled || event.priority < pubsub_sink.min_priority {
+            return;
+        }
+        let channels: Vec<String> = pubsub_sink
+            .channels
+            .iter()
+            .filter_map(|t| channel_name(t, &cfg.redis.namespace, event))
+            .collect();
+        match pubsub_sink.encoding.encode(event) {
+            Ok(message) => self.send(Publication { channels, message }),
+            Err(e) => error!("failed to encode event: {:?}", e),
+        }
+    }
+
+    fn metrics(&self) -> Arc<SinkMetrics> {
+        self.metrics.clone()
+    }
+}
+
+async fn publish_loop(
+    mut client: LazyClient,
+    mut rx: mpsc::Receiver<Publication>,
+    metrics: Arc<SinkMetrics>,
+) {
+    let mut failing = false;
+
+    while let Some(first) = rx.recv().await {
+        let mut batch = vec![first];
+        while batch.len() < BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(publication) => batch.push(publication),
+                Err(_) => break,
+            }
+        }
+        let count = batch.iter().map(|p| p.channels.len() as u64).sum();
+        match publish(&mut client, &batch).await {
+            Ok(()) => {
+                metrics.written.fetch_add(count, Ordering::Relaxed);
+                if failing {
+                    failing = false;
+                    info!("redis pub/sub recovered");
+                }
+            }
+            Err(e) => {
+                SinkMetrics::incr(&metrics.failures);
+                metrics.dropped.fetch_add(count, Ordering::Relaxed);
+                if !failing {
+                    failing = true;
+                    error!("redis pub/sub unavailable, dropping events: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+async fn publish(client: &mut LazyClient, batch: &[Publication]) -> Result<()> {
+    let mut pipeline = client.get().await?.create_pipeline();
+    for publication in batch {
+        for channel in &publication.channels {
+            pipeline.forget(
+                cmd("PUBLISH")
+                    .arg(channel)
+                    .arg(publication.message.as_slice()),
+            );
+        }
+    }
+    pipeline.execute::<()>().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Priority;
+
+    #[test]
+    fn templates() {
+        assert!(check_template("{namespace}:events:{type}:{symbol}:{period}:{priority}").is_ok());
+        assert!(check_template("events").is_ok());
+
+        let e = check_template("{namespace}:{sym}").unwrap_err();
+        assert!(e.starts_with("unknown placeholder {sym}"), "{}", e);
+        let e = check_template("{namespace}:{symbol").unwrap_err();
+        assert!(e.starts_with("unclosed '{'"), "{}", e);
+    }
+
+    #[test]
+    fn channel_names() {
+        let mut event = Event::fixture("BTCUSDT", 0);
+        event.priority = Priority::Critical;
+        assert_eq!(
+            channel_name(
+                "{namespace}:{type}:{symbol}:{period}:{priority}",
+                "perpx",
+                &event
+            )
+            .as_deref(),
+            Some("perpx:ConsecutiveMove:BTCUSDT:5m:critical")
+        );
+
+        // 全市场事件没有交易对，不发布到交易对频道
+        event.symbol = String::new();
+        assert!(channel_name("{namespace}:{symbol}", "perpx", &event).is_none());
+        assert_eq!(
+            channel_name("{namespace}:{type}", "perpx", &event).as_deref(),
+            Some("perpx:ConsecutiveMove")
+        );
+    }
+}
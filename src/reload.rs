@@ -0,0 +1,187 @@
+use crate::config::{load_config, Config, SymbolsConfig};
+use crate::symbols::SymbolFilter;
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+
+// 检查配置文件修改时间的间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// ========== 配置热加载 ==========
+// 配置文件修改或收到 SIGHUP 时重新加载，校验通过后广播给各任务，不影响内存中的行情状态
+pub async fn config_reloader(
+    path: String,
+    config_tx: watch::Sender<Arc<Config>>,
+    filter_tx: Arc<watch::Sender<Arc<SymbolFilter>>>,
+    set_log_level: impl Fn(&str) -> Result<()>,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(e) => {
+            error!("failed to listen for SIGHUP: {:?}", e);
+            None
+        }
+    };
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut last_modified = modified(&path);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let current = modified(&path);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+                info!("config file {} changed, reloading", path);
+            }
+            Some(()) = async { hangup.as_mut()?.recv().await } => {
+                info!("SIGHUP received, reloading {}", path);
+            }
+        }
+
+        let old = config_tx.borrow().clone();
+        if let Err(e) = reload(&path, &old, &config_tx, &filter_tx, &set_log_level) {
+            error!("config reload failed, keeping current config: {}", e);
+        }
+    }
+}
+
+fn reload(
+    path: &str,
+    old: &Config,
+    config_tx: &watch::Sender<Arc<Config>>,
+    filter_tx: &watch::Sender<Arc<SymbolFilter>>,
+    set_log_level: &impl Fn(&str) -> Result<()>,
+) -> Result<()> {
+    let new = load_config(path).map_err(|e| anyhow::anyhow!("{}", e))?;
+
+    // 先全部校验，再应用
+    let filter = if new.symbols != old.symbols {
+        let current = filter_tx.borrow().config().clone();
+        Some(SymbolFilter::new(merge_symbols(
+            &old.symbols,
+            &current,
+            &new.symbols,
+        ))?)
+    } else {
+        None
+    };
+    if new.logging != old.logging {
+        set_log_level(&new.logging.level)?;
+    }
+
+    let restart = new.restart_required(old);
+    if !restart.is_empty() {
+        warn!(
+            "config changes require restart and are not applied: {}",
+            restart.join(", ")
+        );
+    }
+    let live = new.live_changes(old);
+    if let Some(filter) = filter {
+        filter_tx.send_replace(Arc::new(filter));
+    }
+    if !live.is_empty() {
+        config_tx.send_replace(Arc::new(new.merge_live(old)));
+        info!("config reloaded: {}", live.join(", "));
+    }
+    Ok(())
+}
+
+// 合并 [symbols]：配置文件中改动的字段（disabled 按交易对）以文件为准，
+// 其余保留管理接口的运行时修改
+fn merge_symbols(
+    old: &SymbolsConfig,
+    current: &SymbolsConfig,
+    new: &SymbolsConfig,
+) -> SymbolsConfig {
+    let pick = |old: &Vec<String>, current: &Vec<String>, new: &Vec<String>| {
+        if new != old {
+            new.clone()
+        } else {
+            current.clone()
+        }
+    };
+    let mut disabled = current.disabled.clone();
+    let keys = old.disabled.keys().chain(new.disabled.keys());
+    for symbol in keys {
+        let (before, after) = (old.disabled.get(symbol), new.disabled.get(symbol));
+        if before == after {
+            continue;
+        }
+        match after {
+            Some(types) => disabled.insert(symbol.clone(), types.clone()),
+            None => disabled.remove(symbol),
+        };
+    }
+    SymbolsConfig {
+        include: pick(&old.include, &current.include, &new.include),
+        exclude: pick(&old.exclude, &current.exclude, &new.exclude),
+        disabled,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EventType;
+    use std::collections::HashSet;
+
+    fn symbols(include: &[&str], disabled: &[(&str, EventType)]) -> SymbolsConfig {
+        SymbolsConfig {
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: Vec::new(),
+            disabled: disabled
+                .iter()
+                .map(|(symbol, t)| (symbol.to_string(), HashSet::from([*t])))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reload_keeps_admin_changes() {
+        let old = symbols(&["*USDT"], &[]);
+        // 管理接口修改了 include，关闭了 ETHUSDT 的检测器
+        let current = symbols(&["BTCUSDT"], &[("ETHUSDT", EventType::ConsecutiveMove)]);
+        // 配置文件只新增了 exclude 和 SOLUSDT 的开关
+        let mut new = symbols(&["*USDT"], &[("SOLUSDT", EventType::MarketRanking)]);
+        new.exclude = vec!["*USDC".into()];
+
+        let merged = merge_symbols(&old, &current, &new);
+        assert_eq!(merged.include, vec!["BTCUSDT".to_string()]);
+        assert_eq!(merged.exclude, vec!["*USDC".to_string()]);
+        assert_eq!(
+            merged.disabled,
+            symbols(
+                &[],
+                &[
+                    ("ETHUSDT", EventType::ConsecutiveMove),
+                    ("SOLUSDT", EventType::MarketRanking)
+                ]
+            )
+            .disabled
+        );
+    }
+
+    #[test]
+    fn reload_overrides_changed_fields() {
+        let old = symbols(&["*USDT"], &[("ETHUSDT", EventType::ConsecutiveMove)]);
+        let current = symbols(&["BTCUSDT"], &[("ETHUSDT", EventType::MarketRanking)]);
+        // 配置文件修改了 include，删除了 ETHUSDT 的开关
+        let new = symbols(&["ETHUSDT"], &[]);
+
+        let merged = merge_symbols(&old, &current, &new);
+        assert_eq!(merged.include, vec!["ETHUSDT".to_string()]);
+        assert!(merged.disabled.is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+use super::rest::ApiError;
+use super::AppState;
+use crate::config::SymbolsConfig;
+use crate::symbols::SymbolFilter;
+use crate::types::EventType;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::Json;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tracing::info;
+
+#[derive(Deserialize)]
+pub struct SymbolsUpdate {
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+}
+
+impl AppState {
+    // 校验 Authorization: Bearer <admin_token>，按常量时间比较
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), ApiError> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match (&self.admin_token, token) {
+            (Some(expected), Some(token))
+                if bool::from(expected.as_bytes().ct_eq(token.as_bytes())) =>
+            {
+                Ok(())
+            }
+            _ => Err(ApiError(StatusCode::UNAUTHORIZED, "unauthorized".into())),
+        }
+    }
+
+    // 修改过滤规则，校验通过后广播给所有 worker 和事件分发器
+    fn update_symbols(
+        &self,
+        update: impl FnOnce(&mut SymbolsConfig),
+    ) -> Result<Json<SymbolsConfig>, ApiError> {
+        let mut result = Ok(());
+        self.filter.send_if_modified(|current| {
+            let mut config = current.config().clone();
+            update(&mut config);
+            match SymbolFilter::new(config) {
+                Ok(filter) => {
+                    *current = Arc::new(filter);
+                    true
+                }
+                Err(e) => {
+                    result = Err(e);
+                    false
+                }
+            }
+        });
+        result.map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+        let config = self.filter.borrow().config().clone();
+        info!("symbol settings updated: {:?}", config);
+        Ok(Json(config))
+    }
+}
+
+// GET /admin/symbols
+pub async fn get_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<SymbolsConfig>, ApiError> {
+    state.authorize(&headers)?;
+    let config = state.filter.borrow().config().clone();
+    Ok(Json(config))
+}
+
+// PUT /admin/symbols，只替换请求中给出的 include/exclude
+pub async fn put_symbols(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<SymbolsUpdate>,
+) -> Result<Json<SymbolsConfig>, ApiError> {
+    state.authorize(&headers)?;
+    state.update_symbols(|config| {
+        if let Some(include) = body.include {
+            config.include = include;
+        }
+        if let Some(exclude) = body.exclude {
+            config.exclude = exclude;
+        }
+    })
+}
+
+// PUT /admin/detectors/{symbol}，设置交易对关闭的检测器，"*" 表示所有交易对
+pub async fn put_detectors(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+    Json(disabled): Json<HashSet<EventType>>,
+) -> Result<Json<SymbolsConfig>, ApiError> {
+    state.authorize(&headers)?;
+    let symbol = symbol.to_uppercase();
+    state.update_symbols(|config| {
+        if disabled.is_empty() {
+            config.disabled.remove(&symbol);
+        } else {
+            config.disabled.insert(symbol, disabled);
+        }
+    })
+}
+
+// DELETE /admin/detectors/{symbol}，重新开启交易对的所有检测器
+pub async fn delete_detectors(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(symbol): Path<String>,
+) -> Result<Json<SymbolsConfig>, ApiError> {
+    state.authorize(&headers)?;
+    let symbol = symbol.to_uppercase();
+    state.update_symbols(|config| {
+        config.disabled.remove(&symbol);
+    })
+}
@@ -0,0 +1,119 @@
+use crate::types::{Event, Interval, Kline};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+// 推送给 WebSocket 客户端的数据
+#[derive(Debug, Clone)]
+pub enum Push {
+    Event(Arc<Event>),
+    Kline(Arc<KlineUpdate>),
+}
+
+// k线更新
+#[derive(Debug, Clone, Serialize)]
+pub struct KlineUpdate {
+    pub symbol: String,
+    pub interval: Interval,
+    pub kline: Kline,
+    pub closed: bool, // 是否已收盘
+}
+
+// ========== 推送中心 ==========
+// worker 和事件分发器只做非阻塞的 broadcast，慢客户端由各自的连接任务处理，不会影响上游
+// 事件和k线使用不同的广播通道，只订阅事件的客户端不受高频k线更新影响
+// 同时保存最近发出的事件，供 REST 接口查询
+#[derive(Clone)]
+pub struct Hub {
+    events: broadcast::Sender<Push>,
+    klines: broadcast::Sender<Push>,
+    recent: Arc<Mutex<VecDeque<Arc<Event>>>>,
+    recent_capacity: usize,
+}
+
+impl Hub {
+    pub fn new(capacity: usize, recent_capacity: usize) -> Self {
+        let (events, _) = broadcast::channel(capacity.max(1));
+        let (klines, _) = broadcast::channel(capacity.max(1));
+        Self {
+            events,
+            klines,
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(recent_capacity))),
+            recent_capacity,
+        }
+    }
+
+    // 记录最近的事件，超过容量时丢弃最早的
+    pub fn record(&self, event: Arc<Event>) {
+        if self.recent_capacity == 0 {
+            return;
+        }
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() >= self.recent_capacity {
+            recent.pop_front();
+        }
+        recent.push_back(event);
+    }
+
+    // 最近的事件，从新到旧
+    pub fn recent(&self) -> Vec<Arc<Event>> {
+        self.recent.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    // 没有客户端订阅时跳过构造推送数据
+    pub fn has_event_subscribers(&self) -> bool {
+        self.events.receiver_count() > 0
+    }
+
+    pub fn has_kline_subscribers(&self) -> bool {
+        self.klines.receiver_count() > 0
+    }
+
+    pub fn publish(&self, push: Push) {
+        let tx = match push {
+            Push::Event(_) => &self.events,
+            Push::Kline(_) => &self.klines,
+        };
+        let _ = tx.send(push);
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Push> {
+        self.events.subscribe()
+    }
+
+    pub fn subscribe_klines(&self) -> broadcast::Receiver<Push> {
+        self.klines.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn klines_do_not_lag_event_subscribers() {
+        let hub = Hub::new(4, 0);
+        let mut events = hub.subscribe_events();
+        assert!(hub.has_event_subscribers());
+        assert!(!hub.has_kline_subscribers());
+        let mut klines = hub.subscribe_klines();
+        for i in 0..10 {
+            hub.publish(Push::Kline(Arc::new(KlineUpdate {
+                symbol: "BTCUSDT".into(),
+                interval: Interval::Min5,
+                kline: Kline::new(i, 100.0, 1.0),
+                closed: false,
+            })));
+        }
+        let event = Event::fixture("BTCUSDT", 0);
+        hub.publish(Push::Event(Arc::new(event)));
+
+        assert!(matches!(events.try_recv(), Ok(Push::Event(_))));
+        assert!(matches!(
+            klines.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(6))
+        ));
+        assert!(matches!(klines.try_recv(), Ok(Push::Kline(_))));
+    }
+}
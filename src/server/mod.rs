@@ -0,0 +1,71 @@
+pub mod admin;
+pub mod hub;
+pub mod rest;
+pub mod ws;
+
+use crate::config::ApiConfig;
+use crate::metrics::SinkMetrics;
+use crate::symbols::SymbolFilter;
+use crate::types::Message;
+use axum::routing::{get, put};
+use axum::Router;
+use hub::Hub;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tracing::info;
+
+// 各接口共享的状态
+#[derive(Clone)]
+pub struct AppState {
+    pub hub: Hub,
+    pub client_buffer: usize,
+    pub workers: Arc<Vec<mpsc::Sender<Message>>>, // 按 assign_worker 分片的 worker 通道
+    pub query_timeout: u64,
+    pub filter: Arc<watch::Sender<Arc<SymbolFilter>>>, // 交易对过滤和检测器开关
+    pub admin_token: Option<String>,
+    pub sinks: Vec<Arc<SinkMetrics>>, // 各 sink 的写出指标
+}
+
+// ========== 内置 HTTP/WebSocket 服务 ==========
+pub async fn serve(
+    config: ApiConfig,
+    hub: Hub,
+    workers: Arc<Vec<mpsc::Sender<Message>>>,
+    filter: Arc<watch::Sender<Arc<SymbolFilter>>>,
+    sinks: Vec<Arc<SinkMetrics>>,
+) -> anyhow::Result<()> {
+    let state = AppState {
+        hub,
+        client_buffer: config.client_buffer,
+        workers,
+        query_timeout: config.query_timeout,
+        filter,
+        admin_token: config.admin_token.clone(),
+        sinks,
+    };
+    let mut app = Router::new()
+        .route("/ws", get(ws::ws_handler))
+        .route("/symbols", get(rest::symbols))
+        .route("/klines/{symbol}/{interval}", get(rest::klines))
+        .route("/funding/{symbol}", get(rest::funding))
+        .route("/events/recent", get(rest::recent_events))
+        .route("/metrics", get(rest::metrics));
+    // 配置了 admin_token 才开放管理接口
+    if config.admin_token.is_some() {
+        app = app
+            .route(
+                "/admin/symbols",
+                get(admin::get_settings).put(admin::put_symbols),
+            )
+            .route(
+                "/admin/detectors/{symbol}",
+                put(admin::put_detectors).delete(admin::delete_detectors),
+            );
+    }
+    let app = app.with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(&config.listen).await?;
+    info!("🚀 API server listening on {}", config.listen);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use super::AppState;
+use crate::helper::assign_worker;
+use crate::metrics::SinkStats;
+use crate::types::{Event, Interval, Message, Query, SymbolState};
+use axum::extract::{Path, Query as QueryParams, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+pub struct LimitParams {
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct RecentParams {
+    limit: Option<usize>,
+    symbol: Option<String>,
+}
+
+// 接口错误，返回 {"error": "..."}
+pub struct ApiError(pub StatusCode, pub String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl AppState {
+    // 把查询发给 worker 并等待结果，worker 繁忙或已退出时返回 503
+    async fn ask<T>(
+        &self,
+        idx: usize,
+        query: impl FnOnce(oneshot::Sender<T>) -> Query,
+    ) -> Result<T, ApiError> {
+        let (reply, rx) = oneshot::channel();
+        let unavailable = || ApiError(StatusCode::SERVICE_UNAVAILABLE, "worker unavailable".into());
+        let timeout = Duration::from_millis(self.query_timeout);
+        tokio::time::timeout(timeout, async {
+            self.workers[idx]
+                .send(Message::Query(query(reply)))
+                .await
+                .ok()?;
+            rx.await.ok()
+        })
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(unavailable)
+    }
+
+    fn worker_of(&self, symbol: &str) -> usize {
+        assign_worker(symbol, self.workers.len())
+    }
+}
+
+// GET /symbols
+pub async fn symbols(State(state): State<AppState>) -> Result<Json<Vec<SymbolState>>, ApiError> {
+    let mut all = Vec::new();
+    for idx in 0..state.workers.len() {
+        all.extend(state.ask(idx, Query::Symbols).await?);
+    }
+    all.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    Ok(Json(all))
+}
+
+// GET /klines/{symbol}/{interval}?limit=
+pub async fn klines(
+    State(state): State<AppState>,
+    Path((symbol, interval)): Path<(String, String)>,
+    QueryParams(params): QueryParams<LimitParams>,
+) -> Result<Response, ApiError> {
+    let interval = Interval::from_str(&interval).map_err(|_| {
+        ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("unknown interval: {}", interval),
+        )
+    })?;
+    let symbol = symbol.to_uppercase();
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    let klines = state
+        .ask(state.worker_of(&symbol), |reply| Query::Klines {
+            symbol: symbol.clone(),
+            interval,
+            limit,
+            reply,
+        })
+        .await?
+        .ok_or_else(|| not_found(&symbol))?;
+    Ok(Json(klines).into_response())
+}
+
+// GET /funding/{symbol}
+pub async fn funding(
+    State(state): State<AppState>,
+    Path(symbol): Path<String>,
+) -> Result<Response, ApiError> {
+    let symbol = symbol.to_uppercase();
+    let funding = state
+        .ask(state.worker_of(&symbol), |reply| Query::Funding {
+            symbol: symbol.clone(),
+            reply,
+        })
+        .await?
+        .ok_or_else(|| not_found(&symbol))?;
+    Ok(Json(funding).into_response())
+}
+
+// GET /events/recent?limit=&symbol=
+pub async fn recent_events(
+    State(state): State<AppState>,
+    QueryParams(params): QueryParams<RecentParams>,
+) -> Json<Vec<Event>> {
+    let symbol = params.symbol.map(|s| s.to_uppercase());
+    let events = state
+        .hub
+        .recent()
+        .into_iter()
+        .filter(|e| symbol.as_ref().is_none_or(|s| e.symbol == *s))
+        .take(params.limit.unwrap_or(DEFAULT_LIMIT))
+        .map(|e| e.as_ref().clone())
+        .collect();
+    Json(events)
+}
+
+// GET /metrics
+pub async fn metrics(State(state): State<AppState>) -> Json<Vec<SinkStats>> {
+    Json(state.sinks.iter().map(|s| s.stats()).collect())
+}
+
+fn not_found(symbol: &str) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, format!("unknown symbol: {}", symbol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hub::Hub;
+    use super::*;
+    use crate::symbols::SymbolFilter;
+    use crate::types::Kline;
+    use std::sync::Arc;
+    use tokio::sync::{mpsc, watch};
+
+    // 模拟 worker：只认识 BTCUSDT，reply 为 false 时收到查询不回复
+    fn state(reply: bool, hub: Hub) -> AppState {
+        let (tx, mut rx) = mpsc::channel::<Message>(8);
+        tokio::spawn(async move {
+            let mut pending = Vec::new();
+            while let Some(msg) = rx.recv().await {
+                let Message::Query(Query::Klines {
+                    symbol,
+                    interval,
+                    limit,
+                    reply: sender,
+                }) = msg
+                else {
+                    continue;
+                };
+                if !reply {
+                    pending.push(sender);
+                    continue;
+                }
+                let klines = (symbol == "BTCUSDT" && interval == Interval::Hour1)
+                    .then(|| vec![Kline::new(0, 100.0, 1.0); limit]);
+                let _ = sender.send(klines);
+            }
+        });
+        let filter = SymbolFilter::new(Default::default()).unwrap();
+        AppState {
+            hub,
+            client_buffer: 1,
+            workers: Arc::new(vec![tx]),
+            query_timeout: 50,
+            filter: Arc::new(watch::channel(Arc::new(filter)).0),
+            admin_token: None,
+            sinks: Vec::new(),
+        }
+    }
+
+    async fn get_klines(state: &AppState, symbol: &str, interval: &str) -> StatusCode {
+        let result = klines(
+            State(state.clone()),
+            Path((symbol.into(), interval.into())),
+            QueryParams(LimitParams { limit: Some(2) }),
+        )
+        .await;
+        match result {
+            Ok(response) => response.status(),
+            Err(e) => e.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn klines_status() {
+        let state = state(true, Hub::new(1, 0));
+        // 交易对转成大写后查询 worker
+        assert_eq!(get_klines(&state, "btcusdt", "1h").await, StatusCode::OK);
+        assert_eq!(
+            get_klines(&state, "BTCUSDT", "2h").await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            get_klines(&state, "ETHUSDT", "1h").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn worker_timeout() {
+        let state = state(false, Hub::new(1, 0));
+        assert_eq!(
+            get_klines(&state, "BTCUSDT", "1h").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn recent_events_filter() {
+        let hub = Hub::new(1, 10);
+        for (i, symbol) in ["BTCUSDT", "ETHUSDT", "BTCUSDT", "BTCUSDT"]
+            .iter()
+            .enumerate()
+        {
+            hub.record(Arc::new(Event::fixture(symbol, i as u64)));
+        }
+        let state = state(true, hub);
+        let recent = |limit, symbol: Option<&str>| {
+            recent_events(
+                State(state.clone()),
+                QueryParams(RecentParams {
+                    limit,
+                    symbol: symbol.map(str::to_string),
+                }),
+            )
+        };
+
+        // 从新到旧
+        let Json(events) = recent(None, None).await;
+        let timestamps: Vec<u64> = events.iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, vec![3, 2, 1, 0]);
+
+        let Json(events) = recent(Some(2), Some("btcusdt")).await;
+        let timestamps: Vec<u64> = events.iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, vec![3, 2]);
+
+        let Json(events) = recent(None, Some("ETHUSDT")).await;
+        assert_eq!(events.len(), 1);
+    }
+}
@@ -0,0 +1,332 @@
+use super::hub::Push;
+use super::AppState;
+use crate::types::{EventType, Priority};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::IntoResponse;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+#[derive(Deserialize)]
+pub struct WsParams {
+    topics: Option<String>, // 连接时订阅的主题，逗号分隔
+}
+
+// 客户端发来的订阅请求
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request {
+    Subscribe {
+        topics: Vec<String>,
+        #[serde(default)]
+        filter: Option<EventFilter>,
+    },
+    Unsubscribe {
+        topics: Vec<String>,
+    },
+}
+
+// 事件过滤条件，只作用于 events 主题
+#[derive(Deserialize, Default)]
+struct EventFilter {
+    min_priority: Option<Priority>,
+    event_types: Option<HashSet<EventType>>,
+}
+
+#[derive(Serialize)]
+struct Outgoing<'a, T: Serialize> {
+    topic: &'a str,
+    data: &'a T,
+}
+
+// 客户端订阅状态
+#[derive(Default)]
+struct Subscription {
+    topics: HashSet<String>, // events / events:<symbol> / kline:<symbol>:<interval>
+    filter: EventFilter,
+}
+
+impl Subscription {
+    fn wants_klines(&self) -> bool {
+        self.topics.iter().any(|t| t.starts_with("kline:"))
+    }
+
+    fn apply(&mut self, request: Request) {
+        match request {
+            Request::Subscribe { topics, filter } => {
+                self.topics.extend(topics);
+                if let Some(filter) = filter {
+                    self.filter = filter;
+                }
+            }
+            Request::Unsubscribe { topics } => {
+                for topic in topics {
+                    self.topics.remove(&topic);
+                }
+            }
+        }
+    }
+
+    // 匹配订阅的主题并序列化，不匹配时返回 None
+    fn render(&self, push: &Push) -> Option<String> {
+        match push {
+            Push::Event(event) => {
+                let symbol_topic = format!("events:{}", event.symbol);
+                let topic = if self.topics.contains(&symbol_topic) {
+                    symbol_topic.as_str()
+                } else if self.topics.contains("events") {
+                    "events"
+                } else {
+                    return None;
+                };
+                if self.filter.min_priority.is_some_and(|p| event.priority < p)
+                    || self
+                        .filter
+                        .event_types
+                        .as_ref()
+                        .is_some_and(|types| !types.contains(&event.event_type()))
+                {
+                    return None;
+                }
+                serde_json::to_string(&Outgoing {
+                    topic,
+                    data: event.as_ref(),
+                })
+                .ok()
+            }
+            Push::Kline(update) => {
+                let topic = format!("kline:{}:{}", update.symbol, update.interval);
+                if !self.topics.contains(&topic) {
+                    return None;
+                }
+                serde_json::to_string(&Outgoing {
+                    topic: &topic,
+                    data: update.as_ref(),
+                })
+                .ok()
+            }
+        }
+    }
+}
+
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    Query(params): Query<WsParams>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let topics = params
+        .topics
+        .map(|t| {
+            t.split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect::<HashSet<String>>()
+        })
+        .unwrap_or_default();
+    ws.on_upgrade(move |socket| handle_socket(socket, topics, state))
+}
+
+async fn handle_socket(socket: WebSocket, topics: HashSet<String>, state: AppState) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut subscription = Subscription {
+        topics,
+        ..Default::default()
+    };
+    let mut events = state.hub.subscribe_events();
+    // 有k线主题时才订阅k线广播
+    let mut klines = None;
+
+    // 每个客户端一个有界缓冲区，写不进去说明客户端太慢，直接断开
+    let (tx, mut out_rx) = mpsc::channel::<String>(state.client_buffer.max(1));
+    let writer = tokio::spawn(async move {
+        while let Some(text) = out_rx.recv().await {
+            if sender.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        if subscription.wants_klines() != klines.is_some() {
+            klines = subscription
+                .wants_klines()
+                .then(|| state.hub.subscribe_klines());
+        }
+        tokio::select! {
+            msg = receiver.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = match serde_json::from_str::<Request>(&text) {
+                        Ok(request) => {
+                            subscription.apply(request);
+                            json!({ "op": "subscribed", "topics": subscription.topics })
+                        }
+                        Err(e) => json!({ "op": "error", "message": e.to_string() }),
+                    };
+                    if tx.try_send(reply.to_string()).is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            push = events.recv() => {
+                if !forward(&subscription, push, &tx) {
+                    break;
+                }
+            }
+            push = recv_klines(&mut klines) => {
+                if !forward(&subscription, push, &tx) {
+                    break;
+                }
+            }
+        }
+    }
+    writer.abort();
+    debug!("websocket client closed");
+}
+
+async fn recv_klines(klines: &mut Option<broadcast::Receiver<Push>>) -> Result<Push, RecvError> {
+    match klines {
+        Some(klines) => klines.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// 把推送转给客户端，返回 false 时断开连接
+fn forward(
+    subscription: &Subscription,
+    push: Result<Push, RecvError>,
+    tx: &mpsc::Sender<String>,
+) -> bool {
+    match push {
+        Ok(push) => {
+            if let Some(text) = subscription.render(&push) {
+                if tx.try_send(text).is_err() {
+                    warn!("websocket client too slow, disconnected");
+                    return false;
+                }
+            }
+            true
+        }
+        // 落后于广播时跳过错过的消息并通知客户端，不断开
+        Err(RecvError::Lagged(n)) => {
+            warn!("websocket client lagged, skipped {} messages", n);
+            let _ = tx.try_send(json!({ "op": "lagged", "skipped": n }).to_string());
+            true
+        }
+        Err(RecvError::Closed) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::hub::KlineUpdate;
+    use super::*;
+    use crate::types::{Event, Interval, Kline};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    fn subscribe(json: &str) -> Subscription {
+        let mut subscription = Subscription::default();
+        subscription.apply(serde_json::from_str(json).unwrap());
+        subscription
+    }
+
+    fn event(symbol: &str, priority: Priority) -> Push {
+        let mut event = Event::fixture(symbol, 0);
+        event.priority = priority;
+        Push::Event(Arc::new(event))
+    }
+
+    fn kline(symbol: &str, interval: Interval) -> Push {
+        Push::Kline(Arc::new(KlineUpdate {
+            symbol: symbol.into(),
+            interval,
+            kline: Kline::new(0, 100.0, 1.0),
+            closed: false,
+        }))
+    }
+
+    fn topic(text: Option<String>) -> Option<String> {
+        let value: Value = serde_json::from_str(&text?).unwrap();
+        value["topic"].as_str().map(str::to_string)
+    }
+
+    // 同时订阅 events 和 events:<symbol> 时使用交易对主题
+    #[test]
+    fn symbol_topic_preferred() {
+        let subscription = subscribe(r#"{"op":"subscribe","topics":["events","events:BTCUSDT"]}"#);
+        assert_eq!(
+            topic(subscription.render(&event("BTCUSDT", Priority::Low))),
+            Some("events:BTCUSDT".into())
+        );
+        assert_eq!(
+            topic(subscription.render(&event("ETHUSDT", Priority::Low))),
+            Some("events".into())
+        );
+
+        let subscription = subscribe(r#"{"op":"subscribe","topics":["events:BTCUSDT"]}"#);
+        assert!(subscription
+            .render(&event("ETHUSDT", Priority::Low))
+            .is_none());
+        assert!(!subscription.wants_klines());
+    }
+
+    #[test]
+    fn event_filters() {
+        let subscription = subscribe(
+            r#"{"op":"subscribe","topics":["events"],
+                "filter":{"min_priority":"high","event_types":["ConsecutiveMove"]}}"#,
+        );
+        assert!(subscription
+            .render(&event("BTCUSDT", Priority::Normal))
+            .is_none());
+        assert!(subscription
+            .render(&event("BTCUSDT", Priority::High))
+            .is_some());
+        assert!(subscription
+            .render(&event("BTCUSDT", Priority::Critical))
+            .is_some());
+
+        let subscription = subscribe(
+            r#"{"op":"subscribe","topics":["events"],"filter":{"event_types":["FundingRate"]}}"#,
+        );
+        assert!(subscription
+            .render(&event("BTCUSDT", Priority::Critical))
+            .is_none());
+    }
+
+    #[test]
+    fn kline_topics_and_unsubscribe() {
+        let mut subscription =
+            subscribe(r#"{"op":"subscribe","topics":["kline:BTCUSDT:5m","events"]}"#);
+        assert!(subscription.wants_klines());
+        assert_eq!(
+            topic(subscription.render(&kline("BTCUSDT", Interval::Min5))),
+            Some("kline:BTCUSDT:5m".into())
+        );
+        assert!(subscription
+            .render(&kline("BTCUSDT", Interval::Hour1))
+            .is_none());
+        assert!(subscription
+            .render(&kline("ETHUSDT", Interval::Min5))
+            .is_none());
+
+        // 只取消 k 线主题，不影响 events 和之前的过滤条件
+        subscription.apply(
+            serde_json::from_str(r#"{"op":"unsubscribe","topics":["kline:BTCUSDT:5m"]}"#).unwrap(),
+        );
+        assert!(!subscription.wants_klines());
+        assert!(subscription
+            .render(&kline("BTCUSDT", Interval::Min5))
+            .is_none());
+        assert!(subscription
+            .render(&event("BTCUSDT", Priority::Low))
+            .is_some());
+    }
+}
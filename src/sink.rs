@@ -0,0 +1,54 @@
+use crate::config::Config;
+use crate::metrics::SinkMetrics;
+use crate::types::{ClosedBar, Event};
+use std::sync::Arc;
+
+// ========== 事件输出 ==========
+// 每个 sink 按当前配置决定是否写出，实际写出由各自的后台任务完成，调用方不会被阻塞
+
+pub trait EventSink: Send + Sync {
+    /// 写出一个事件，事件已经过去重并计算了优先级
+    fn send_event(&self, event: &Arc<Event>, cfg: &Config);
+
+    /// 写出一根收盘k线，k线已经过交易对过滤，默认不写出
+    fn send_kline(&self, _bar: &ClosedBar, _cfg: &Config) {}
+
+    /// 配置热加载后调用，用于重建路由规则等
+    fn reload(&self, _cfg: &Config) {}
+
+    fn metrics(&self) -> Arc<SinkMetrics>;
+}
+
+// 把事件和收盘k线分发给所有 sink
+#[derive(Clone, Default)]
+pub struct FanOut {
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl FanOut {
+    pub fn push(&mut self, sink: impl EventSink + 'static) {
+        self.sinks.push(Arc::new(sink));
+    }
+
+    pub fn send_event(&self, event: &Arc<Event>, cfg: &Config) {
+        for sink in &self.sinks {
+            sink.send_event(event, cfg);
+        }
+    }
+
+    pub fn send_kline(&self, bar: &ClosedBar, cfg: &Config) {
+        for sink in &self.sinks {
+            sink.send_kline(bar, cfg);
+        }
+    }
+
+    pub fn reload(&self, cfg: &Config) {
+        for sink in &self.sinks {
+            sink.reload(cfg);
+        }
+    }
+
+    pub fn metrics(&self) -> Vec<Arc<SinkMetrics>> {
+        self.sinks.iter().map(|sink| sink.metrics()).collect()
+    }
+}
@@ -0,0 +1,301 @@
+use crate::encoding::Encoding;
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+// 等待写出的消息
+#[derive(Debug, Clone)]
+pub struct Pending {
+    pub queue_name: String,
+    pub message: Vec<u8>,
+    pub encoding: Encoding,
+}
+
+// ========== 磁盘缓冲 ==========
+// 只追加的二进制文件，每条记录：
+//   u32 队列名长度 + 队列名，u8 编码名长度 + 编码名，u32 消息长度 + 消息（长度均为小端）
+// 读完并且内存中的消息都写出后清空文件；进程重启时重新读取文件中的全部记录，
+// 已经写出但还没清空的记录会再写一次（至少一次）
+// 内容损坏（队列名不是 UTF-8、未知编码）的记录在读取时跳过并记录警告
+pub struct Spool {
+    path: PathBuf,
+    file: File,
+    read_pos: u64, // 下一条未读记录的位置
+    size: u64,     // 文件大小
+    unread: u64,   // 未读记录数
+    max_bytes: u64,
+}
+
+impl Spool {
+    /// 打开磁盘缓冲文件，统计上次退出时留下的记录
+    pub fn open(path: &Path, max_bytes: u64) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = BufReader::new(&mut file);
+        let mut size = 0;
+        let mut unread = 0;
+        let mut corrupt = 0;
+        // 最后一条记录可能没写完整，截断到最后一条完整记录
+        while let Some((record_size, item)) = read_record(&mut reader)? {
+            size += record_size;
+            unread += 1;
+            if item.is_err() {
+                corrupt += 1;
+            }
+        }
+        if corrupt > 0 {
+            warn!(
+                "{} corrupt records in spool {}, they will be skipped",
+                corrupt,
+                path.display()
+            );
+        }
+        if file_size > size {
+            warn!(
+                "truncating {} bytes of incomplete records in spool {}",
+                file_size - size,
+                path.display()
+            );
+            file.set_len(size)?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            read_pos: 0,
+            size,
+            unread,
+            max_bytes,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 未读记录数
+    pub fn unread(&self) -> u64 {
+        self.unread
+    }
+
+    /// 追加一条记录，超过大小上限时返回 false
+    pub fn push(&mut self, item: &Pending) -> Result<bool> {
+        let record_size = record_size(item);
+        if self.size + record_size > self.max_bytes {
+            return Ok(false);
+        }
+        let encoding = item.encoding.name().as_bytes();
+        let mut record = Vec::with_capacity(record_size as usize);
+        record.extend_from_slice(&(item.queue_name.len() as u32).to_le_bytes());
+        record.extend_from_slice(item.queue_name.as_bytes());
+        record.push(encoding.len() as u8);
+        record.extend_from_slice(encoding);
+        record.extend_from_slice(&(item.message.len() as u32).to_le_bytes());
+        record.extend_from_slice(&item.message);
+        self.file.write_all(&record)?;
+        self.size += record_size;
+        self.unread += 1;
+        Ok(true)
+    }
+
+    /// 按写入顺序读取最多 max 条记录
+    pub fn read_batch(&mut self, max: usize) -> Result<Vec<Pending>> {
+        self.file.seek(SeekFrom::Start(self.read_pos))?;
+        let mut reader = BufReader::new(&mut self.file);
+        let mut items = Vec::new();
+        while items.len() < max {
+            let Some((record_size, item)) = read_record(&mut reader)? else {
+                break;
+            };
+            self.read_pos += record_size;
+            self.unread -= 1;
+            match item {
+                Ok(item) => items.push(item),
+                Err(e) => warn!("skipping corrupt spool record: {:?}", e),
+            }
+        }
+        Ok(items)
+    }
+
+    /// 所有记录都已写出，清空文件
+    pub fn clear(&mut self) -> Result<()> {
+        if self.size > 0 {
+            self.file.set_len(0)?;
+        }
+        self.read_pos = 0;
+        self.size = 0;
+        self.unread = 0;
+        Ok(())
+    }
+}
+
+fn record_size(item: &Pending) -> u64 {
+    (4 + item.queue_name.len() + 1 + item.encoding.name().len() + 4 + item.message.len()) as u64
+}
+
+// 读取一条记录和它的大小，文件结束或者记录不完整时返回 None，内容损坏时返回解析错误
+fn read_record(reader: &mut impl Read) -> Result<Option<(u64, Result<Pending>)>> {
+    let Some(queue_name) = read_field(reader, 4)? else {
+        return Ok(None);
+    };
+    let Some(encoding) = read_field(reader, 1)? else {
+        return Ok(None);
+    };
+    let Some(message) = read_field(reader, 4)? else {
+        return Ok(None);
+    };
+    let size = (4 + queue_name.len() + 1 + encoding.len() + 4 + message.len()) as u64;
+    Ok(Some((size, parse_record(queue_name, encoding, message))))
+}
+
+fn parse_record(queue_name: Vec<u8>, encoding: Vec<u8>, message: Vec<u8>) -> Result<Pending> {
+    let encoding = String::from_utf8(encoding)?;
+    Ok(Pending {
+        queue_name: String::from_utf8(queue_name)?,
+        message,
+        encoding: Encoding::from_name(&encoding)
+            .ok_or_else(|| anyhow!("unknown encoding in spool: {}", encoding))?,
+    })
+}
+
+// 读取长度前缀（len_bytes 字节，小端）和内容
+fn read_field(reader: &mut impl Read, len_bytes: usize) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    if !read_exact(reader, &mut len[..len_bytes])? {
+        return Ok(None);
+    }
+    // 长度损坏时不按长度预先分配内存
+    let len = u32::from_le_bytes(len) as usize;
+    let mut data = Vec::new();
+    reader.take(len as u64).read_to_end(&mut data)?;
+    if data.len() < len {
+        return Ok(None);
+    }
+    Ok(Some(data))
+}
+
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("perpx-spool-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn item(queue_name: &str, message: &str) -> Pending {
+        Pending {
+            queue_name: queue_name.to_string(),
+            message: message.as_bytes().to_vec(),
+            encoding: Encoding::Json,
+        }
+    }
+
+    fn messages(items: &[Pending]) -> Vec<&str> {
+        items
+            .iter()
+            .map(|i| std::str::from_utf8(&i.message).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ordering_and_reopen() {
+        let path = temp_path("ordering");
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        for i in 0..5 {
+            assert!(spool.push(&item("events", &i.to_string())).unwrap());
+        }
+        assert_eq!(messages(&spool.read_batch(2).unwrap()), ["0", "1"]);
+        assert_eq!(spool.unread(), 3);
+        // 重启后重新读取全部记录
+        drop(spool);
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        assert_eq!(spool.unread(), 5);
+        assert_eq!(
+            messages(&spool.read_batch(10).unwrap()),
+            ["0", "1", "2", "3", "4"]
+        );
+        spool.clear().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_tail() {
+        let path = temp_path("truncated");
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        spool.push(&item("events", "a")).unwrap();
+        spool.push(&item("events", "b")).unwrap();
+        drop(spool);
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        assert_eq!(spool.unread(), 1);
+        assert_eq!(messages(&spool.read_batch(10).unwrap()), ["a"]);
+        // 截断后可以继续追加
+        spool.push(&item("events", "c")).unwrap();
+        assert_eq!(messages(&spool.read_batch(10).unwrap()), ["c"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_records_skipped() {
+        let path = temp_path("corrupt");
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        spool.push(&item("events", "a")).unwrap();
+        drop(spool);
+        // 未知编码和非 UTF-8 队列名
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        for (queue_name, encoding) in [
+            (&b"events"[..], &b"xml"[..]),
+            (&[0xff, 0xfe][..], &b"json"[..]),
+        ] {
+            file.write_all(&(queue_name.len() as u32).to_le_bytes())
+                .unwrap();
+            file.write_all(queue_name).unwrap();
+            file.write_all(&[encoding.len() as u8]).unwrap();
+            file.write_all(encoding).unwrap();
+            file.write_all(&1u32.to_le_bytes()).unwrap();
+            file.write_all(b"x").unwrap();
+        }
+        drop(file);
+        let mut spool = Spool::open(&path, 1 << 20).unwrap();
+        spool.push(&item("events", "b")).unwrap();
+        assert_eq!(spool.unread(), 4);
+        assert_eq!(messages(&spool.read_batch(10).unwrap()), ["a", "b"]);
+        assert_eq!(spool.unread(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn size_limit() {
+        let path = temp_path("limit");
+        let record = record_size(&item("events", "a"));
+        let mut spool = Spool::open(&path, record * 2).unwrap();
+        assert!(spool.push(&item("events", "a")).unwrap());
+        assert!(spool.push(&item("events", "b")).unwrap());
+        assert!(!spool.push(&item("events", "c")).unwrap());
+        assert_eq!(spool.unread(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use crate::config::SymbolsConfig;
+use crate::types::EventType;
+use anyhow::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::RegexSet;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+// 所有交易对共用的检测器开关
+pub const ALL_SYMBOLS: &str = "*";
+
+// worker 和事件分发器通过该通道获取最新的过滤规则
+pub type FilterReceiver = watch::Receiver<Arc<SymbolFilter>>;
+
+// 一组交易对匹配规则，默认 glob，"re:" 开头为正则
+pub struct Patterns {
+    globs: GlobSet,
+    regexes: RegexSet,
+}
+
+impl Patterns {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut globs = GlobSetBuilder::new();
+        let mut regexes = Vec::new();
+        for pattern in patterns {
+            match pattern.strip_prefix("re:") {
+                Some(re) => regexes.push(re),
+                None => {
+                    globs.add(Glob::new(pattern)?);
+                }
+            }
+        }
+        Ok(Self {
+            globs: globs.build()?,
+            regexes: RegexSet::new(regexes)?,
+        })
+    }
+
+    pub fn is_match(&self, symbol: &str) -> bool {
+        self.globs.is_match(symbol) || self.regexes.is_match(symbol)
+    }
+}
+
+/// 校验单个匹配规则
+pub fn check_pattern(pattern: &str) -> Result<()> {
+    Patterns::new(&[pattern.to_string()]).map(|_| ())
+}
+
+// ========== 交易对过滤 ==========
+pub struct SymbolFilter {
+    config: SymbolsConfig,
+    include: Option<Patterns>,
+    exclude: Patterns,
+}
+
+impl SymbolFilter {
+    pub fn new(config: SymbolsConfig) -> Result<Self> {
+        let include = if config.include.is_empty() {
+            None
+        } else {
+            Some(Patterns::new(&config.include)?)
+        };
+        let exclude = Patterns::new(&config.exclude)?;
+        Ok(Self {
+            config,
+            include,
+            exclude,
+        })
+    }
+
+    pub fn config(&self) -> &SymbolsConfig {
+        &self.config
+    }
+
+    /// 交易对是否需要处理
+    pub fn allows(&self, symbol: &str) -> bool {
+        self.include.as_ref().is_none_or(|p| p.is_match(symbol)) && !self.exclude.is_match(symbol)
+    }
+
+    /// 交易对上的某个检测器是否开启，全市场事件（symbol 为空）只看 "*" 的开关
+    pub fn enabled(&self, symbol: &str, event_type: EventType) -> bool {
+        (symbol.is_empty() || self.allows(symbol))
+            && ![symbol, ALL_SYMBOLS].iter().any(|key| {
+                self.config
+                    .disabled
+                    .get(*key)
+                    .is_some_and(|types| types.contains(&event_type))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    fn strings(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn filter(include: &[&str], exclude: &[&str], disabled: &[(&str, EventType)]) -> SymbolFilter {
+        let mut map: HashMap<String, HashSet<EventType>> = HashMap::new();
+        for (symbol, t) in disabled {
+            map.entry(symbol.to_string()).or_default().insert(*t);
+        }
+        SymbolFilter::new(SymbolsConfig {
+            include: strings(include),
+            exclude: strings(exclude),
+            disabled: map,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn glob_and_regex() {
+        let patterns = Patterns::new(&strings(&["*USDT", "re:^ETH.*C$"])).unwrap();
+        assert!(patterns.is_match("BTCUSDT"));
+        assert!(patterns.is_match("ETHUSDC"));
+        assert!(!patterns.is_match("BTCUSDC"));
+        assert!(!patterns.is_match("BTCUSDT_250328"));
+
+        assert!(check_pattern("re:^BTC(").is_err());
+        assert!(check_pattern("BTC[").is_err());
+        assert!(SymbolFilter::new(SymbolsConfig {
+            exclude: strings(&["re:("]),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        // 没有 include 时全部处理
+        let all = filter(&[], &[], &[]);
+        assert!(all.allows("BTCUSDT"));
+
+        // exclude 优先于 include
+        let f = filter(&["*USDT"], &["re:^1000", "LUNAUSDT"], &[]);
+        assert!(f.allows("BTCUSDT"));
+        assert!(!f.allows("BTCUSDC"));
+        assert!(!f.allows("1000PEPEUSDT"));
+        assert!(!f.allows("LUNAUSDT"));
+    }
+
+    #[test]
+    fn detector_toggles() {
+        let f = filter(
+            &["*USDT"],
+            &[],
+            &[
+                ("ETHUSDT", EventType::ConsecutiveMove),
+                (ALL_SYMBOLS, EventType::FundingRate),
+            ],
+        );
+        assert!(f.enabled("BTCUSDT", EventType::ConsecutiveMove));
+        assert!(!f.enabled("ETHUSDT", EventType::ConsecutiveMove));
+        assert!(f.enabled("ETHUSDT", EventType::VolatilitySpike));
+        // "*" 对所有交易对生效
+        assert!(!f.enabled("BTCUSDT", EventType::FundingRate));
+        // 不处理的交易对上检测器都关闭
+        assert!(!f.enabled("BTCUSDC", EventType::VolatilitySpike));
+        // 全市场事件只看 "*"
+        assert!(f.enabled("", EventType::MarketBreadth));
+        assert!(!f.enabled("", EventType::FundingRate));
+    }
+}
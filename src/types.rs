@@ -1,5 +1,10 @@
+use anyhow::{ensure, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use uuid::Uuid;
 
 // ========== 数据结构 ==========
 #[allow(dead_code)]
@@ -28,27 +33,137 @@ pub struct MarkPrice {
     pub event_time: u64,
     #[serde(rename = "s")]
     pub symbol: String,
+    #[serde(rename = "p")]
+    pub mark_price: String, // 标记价格
+    #[serde(rename = "i")]
+    pub index_price: String, // 现货指数价格
     #[serde(rename = "r")]
     pub funding_rate: String,
     #[serde(rename = "T")]
     pub next_funding_time: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum Message {
     Ticker(Ticker),
     MarkPrice(MarkPrice),
+    Query(Query),
+    FundingInfo(Arc<HashMap<String, u64>>), // fundingInfo 中各交易对的结算周期（毫秒），发给所有 worker
+}
+
+// REST 接口发给 worker 的查询，worker 通过 oneshot 返回内存中的状态快照
+#[derive(Debug)]
+pub enum Query {
+    Symbols(oneshot::Sender<Vec<SymbolState>>),
+    Klines {
+        symbol: String,
+        interval: Interval,
+        limit: usize,
+        reply: oneshot::Sender<Option<Vec<Kline>>>,
+    },
+    Funding {
+        symbol: String,
+        reply: oneshot::Sender<Option<FundingState>>,
+    },
+}
+
+// 交易对当前状态
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolState {
+    pub symbol: String,
+    pub event_time: u64,
+    pub price: f64,
+    pub turnover: f64,                   // 24小时成交额
+    pub returns: HashMap<Interval, f64>, // 最近一个周期长度的滚动涨跌幅
+}
+
+impl From<&SymbolSnapshot> for SymbolState {
+    fn from(s: &SymbolSnapshot) -> Self {
+        Self {
+            symbol: s.symbol.clone(),
+            event_time: s.event_time,
+            price: s.price,
+            turnover: s.turnover,
+            returns: s.returns.iter().copied().collect(),
+        }
+    }
+}
+
+// 交易对资金费率和溢价状态
+#[derive(Debug, Clone, Serialize)]
+pub struct FundingState {
+    pub symbol: String,
+    pub event_time: u64,
+    pub funding_rate: f64,
+    pub annualized_rate: f64,
+    pub next_funding_time: u64,
+    pub funding_interval: u64,    // 结算周期（毫秒）
+    pub interval_confirmed: bool, // 结算周期是否已经确认（fundingInfo 或观察到的结算），否则为默认的8小时
+    pub mark_price: f64,
+    pub index_price: f64,
+    pub premium: f64,             // mark/index 溢价
+    pub avg_premium: Option<f64>, // 滚动平均溢价
+}
+
+// worker 汇总到全市场聚合器的消息
+#[derive(Debug, Clone)]
+pub enum MarketMessage {
+    Snapshot(SymbolSnapshot),
+    BarClosed(ClosedBar),
+    ExchangeInfo(HashMap<String, SymbolInfo>),
+}
+
+// exchangeInfo 中的交易对信息
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub status: String, // TRADING / PENDING_TRADING / SETTLING / DELIVERING ...
+    #[serde(default)]
+    pub contract_type: String,
+    #[serde(default)]
+    pub onboard_date: u64,
+}
+
+// 已收盘的k线
+#[derive(Debug, Clone, Serialize)]
+pub struct ClosedBar {
+    pub symbol: String,
+    pub interval: Interval,
+    #[serde(flatten)]
+    pub kline: Kline,
 }
 
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+// 单个交易对的最新状态
+#[derive(Debug, Clone)]
+pub struct SymbolSnapshot {
+    pub symbol: String,
+    pub event_time: u64,
+    pub price: f64,
+    pub turnover: f64,                 // 24小时成交额
+    pub returns: Vec<(Interval, f64)>, // 最近一个周期长度的滚动涨跌幅
+}
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub enum Interval {
+    #[serde(rename = "5m")]
     Min5,
+    #[serde(rename = "15m")]
     Min15,
+    #[serde(rename = "1h")]
     Hour1,
+    #[serde(rename = "4h")]
     Hour4,
 }
 
 impl Interval {
+    pub const ALL: [Interval; 4] = [
+        Interval::Min5,
+        Interval::Min15,
+        Interval::Hour1,
+        Interval::Hour4,
+    ];
+
     pub fn seconds(&self) -> u64 {
         match self {
             Interval::Min5 => 300,
@@ -57,18 +172,32 @@ impl Interval {
             Interval::Hour4 => 14400,
         }
     }
+}
+
+impl std::str::FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Interval::ALL
+            .into_iter()
+            .find(|i| i.to_string() == s)
+            .ok_or_else(|| format!("unknown interval: {}", s))
+    }
+}
 
-    pub fn to_string(&self) -> String {
-        match self {
-            Interval::Min5 => "5m".to_string(),
-            Interval::Min15 => "15m".to_string(),
-            Interval::Hour1 => "1h".to_string(),
-            Interval::Hour4 => "4h".to_string(),
-        }
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Interval::Min5 => "5m",
+            Interval::Min15 => "15m",
+            Interval::Hour1 => "1h",
+            Interval::Hour4 => "4h",
+        };
+        f.write_str(s)
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Kline {
     pub open: f64,
     pub high: f64,
@@ -99,32 +228,325 @@ impl Kline {
 }
 
 // 事件枚举
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Deserialize, Serialize)]
 pub enum EventType {
-    ConsecutiveMove, // 连续 N 个周期涨/跌
-    VolatilitySpike, // 异常波动
-    FundingRate,     // 资金费率
+    ConsecutiveMove,       // 连续 N 个周期涨/跌
+    VolatilitySpike,       // 异常波动
+    FundingRate,           // 资金费率
+    FundingCountdown,      // 资金费率结算倒计时
+    FundingIntervalChange, // 资金费率结算周期变化
+    PremiumDeviation,      // 溢价/基差偏离
+    MarketRanking,         // 全市场涨跌幅/成交额排行
+    MarketBreadth,         // 市场宽度进入极端
+    BtcDivergence,         // 相对 BTC 的强弱背离
+    NewListing,            // 新上线交易对
+    Delisting,             // 交易对下架/交割/结算
 }
 
+// 事件结构版本，字段有不兼容变化时递增
+pub const SCHEMA_VERSION: u32 = 1;
+
 // 事件数据结构
-#[derive(Debug, Clone, Serialize)]
+// event_type 与 value 由 payload 展开，value 的结构由 event_type 决定
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Event {
+    pub schema_version: u32,
+    pub id: String, // 事件唯一ID（uuid v4）
     pub symbol: String,
-    pub event_type: EventType,
-    pub period: String,            // "1h" / "5m"
-    pub value: Map<String, Value>, // 实际计算出的指标结果
+    pub period: String, // "1h" / "5m"
+    #[serde(flatten)]
+    pub payload: EventPayload, // 实际计算出的指标结果
     pub timestamp: u64,
+    pub severity: f64,      // 归一化的严重程度，1 表示刚好达到检测阈值
+    pub priority: Priority, // 由分发器根据 severity 计算
+    #[serde(default)]
+    pub suppressed: u64, // 上次发出后被去重抑制的同类事件数量
+}
+
+// 事件优先级
+#[derive(
+    Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+    Critical,
 }
 
 impl Event {
+    pub fn new(
+        symbol: String,
+        period: String,
+        payload: EventPayload,
+        timestamp: u64,
+        severity: f64,
+    ) -> Result<Self> {
+        // 非有限值序列化为 null，下游无法计算优先级
+        ensure!(
+            severity.is_finite(),
+            "invalid severity {} for {} {:?}",
+            severity,
+            symbol,
+            payload.event_type()
+        );
+        Ok(Self {
+            schema_version: SCHEMA_VERSION,
+            id: Uuid::new_v4().to_string(),
+            symbol,
+            period,
+            payload,
+            timestamp,
+            severity,
+            priority: Priority::Low,
+            suppressed: 0,
+        })
+    }
+
+    pub fn event_type(&self) -> EventType {
+        self.payload.event_type()
+    }
+
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).expect("failed to serialize Event to JSON")
     }
+
+    /// 指标结果的 JSON，与消息中的 value 字段相同
+    pub fn value_json(&self) -> String {
+        let payload =
+            serde_json::to_value(&self.payload).expect("failed to serialize EventPayload");
+        payload["value"].to_string()
+    }
+}
+
+// 测试用事件：5m 连续上涨 3 根，severity 1
+#[cfg(test)]
+impl Event {
+    pub fn fixture(symbol: &str, timestamp: u64) -> Self {
+        let payload = EventPayload::ConsecutiveMove(ConsecutiveMove {
+            count: 3,
+            turnover: 1e8,
+            direction: 1,
+        });
+        Self::new(symbol.into(), "5m".into(), payload, timestamp, 1.0).unwrap()
+    }
+}
+
+// 各类事件的指标结果
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "event_type", content = "value")]
+pub enum EventPayload {
+    ConsecutiveMove(ConsecutiveMove),
+    VolatilitySpike(VolatilitySpike),
+    FundingRate(FundingRate),
+    FundingCountdown(FundingCountdown),
+    FundingIntervalChange(FundingIntervalChange),
+    PremiumDeviation(PremiumDeviation),
+    MarketRanking(MarketRanking),
+    MarketBreadth(MarketBreadth),
+    BtcDivergence(BtcDivergence),
+    NewListing(ListingChange),
+    Delisting(ListingChange),
+}
+
+impl EventPayload {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            EventPayload::ConsecutiveMove(_) => EventType::ConsecutiveMove,
+            EventPayload::VolatilitySpike(_) => EventType::VolatilitySpike,
+            EventPayload::FundingRate(_) => EventType::FundingRate,
+            EventPayload::FundingCountdown(_) => EventType::FundingCountdown,
+            EventPayload::FundingIntervalChange(_) => EventType::FundingIntervalChange,
+            EventPayload::PremiumDeviation(_) => EventType::PremiumDeviation,
+            EventPayload::MarketRanking(_) => EventType::MarketRanking,
+            EventPayload::MarketBreadth(_) => EventType::MarketBreadth,
+            EventPayload::BtcDivergence(_) => EventType::BtcDivergence,
+            EventPayload::NewListing(_) => EventType::NewListing,
+            EventPayload::Delisting(_) => EventType::Delisting,
+        }
+    }
+}
+
+// 连续 N 个周期涨/跌
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ConsecutiveMove {
+    pub count: u32,
+    pub turnover: f64, // 24小时成交额
+    pub direction: i8, // 1 上涨，-1 下跌
+}
+
+// 异常波动
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VolatilitySpike {
+    pub amplitude: f64,     // 当前k线振幅
+    pub avg_amplitude: f64, // 之前k线的平均振幅
+    pub volume: f64,
+    pub turnover: f64, // 24小时成交额
+    pub direction: i8, // 1 上涨，-1 下跌
+}
+
+// 资金费率
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FundingRate {
+    pub funding_rate: f64,
+    pub next_funding_time: u64,
+    pub funding_interval_hours: f64,
+    pub annualized_rate: f64,
+}
+
+// 资金费率结算倒计时
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FundingCountdown {
+    pub funding_rate: f64,
+    pub next_funding_time: u64,
+    pub minutes_before: u64,
+    pub funding_interval_hours: f64,
+    pub annualized_rate: f64,
+}
+
+// 资金费率结算周期变化
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FundingIntervalChange {
+    pub old_interval_hours: f64,
+    pub new_interval_hours: f64,
+    pub next_funding_time: u64,
+}
+
+// 溢价/基差偏离
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PremiumDeviation {
+    pub reason: String, // mark_index / last_mark / rolling_avg
+    pub mark_price: f64,
+    pub index_price: f64,
+    pub last_price: Option<f64>,
+    pub premium: f64, // (mark - index) / index
+    pub avg_premium: Option<f64>,
+    pub basis: Option<f64>, // (last - mark) / mark
 }
 
 // 资金费率事件限制
 #[derive(Debug, Clone)]
 pub struct FundingRateLimit {
-    pub rate: f64,  // 当前的资金费率
-    pub time: u64,  // 上次事件发生的时间
+    pub rate: f64, // 当前的资金费率
+    pub time: u64, // 上次事件发生的时间
+}
+
+// 资金费率结算周期状态
+#[derive(Debug, Clone)]
+pub struct FundingSchedule {
+    pub next_funding_time: u64, // 下次结算时间
+    pub interval: Option<u64>,  // 结算周期（毫秒），来自 fundingInfo 或观察到的结算时间变化
+    pub alerted: Vec<u64>,      // 本结算周期内已发送的倒计时提醒（分钟）
+}
+
+impl FundingSchedule {
+    pub fn new(next_funding_time: u64, interval: Option<u64>) -> Self {
+        Self {
+            next_funding_time,
+            interval,
+            alerted: Vec::new(),
+        }
+    }
+}
+
+// 溢价状态，保存最近的溢价样本用于计算滚动均值
+#[derive(Debug, Clone, Default)]
+pub struct PremiumState {
+    pub samples: VecDeque<f64>, // 最近的 mark/index 溢价
+    pub time: u64,              // 上次事件发生的时间
+}
+
+impl PremiumState {
+    /// 加入新样本，返回加入前的滚动均值（样本为空时返回 None）
+    pub fn push(&mut self, premium: f64, window: usize) -> Option<f64> {
+        let avg = if self.samples.is_empty() {
+            None
+        } else {
+            Some(self.samples.iter().sum::<f64>() / self.samples.len() as f64)
+        };
+        self.samples.push_back(premium);
+        while self.samples.len() > window {
+            self.samples.pop_front();
+        }
+        avg
+    }
+}
+
+// 溢价事件的价格快照
+#[derive(Debug, Clone)]
+pub struct PremiumSample {
+    pub mark_price: f64,
+    pub index_price: f64,
+    pub last_price: Option<f64>,  // 最新成交价，尚未收到 ticker 时为 None
+    pub premium: f64,             // (mark - index) / index
+    pub avg_premium: Option<f64>, // 溢价滚动均值
+    pub severity: f64,            // 偏离相对阈值的最大倍数
+}
+
+// 排行榜条目
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RankEntry {
+    pub symbol: String,
+    pub price: f64,
+    pub value: f64, // 涨跌幅或成交额
+}
+
+// 成交额排名变化
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RankChange {
+    pub symbol: String,
+    pub rank: usize,
+    pub prev_rank: usize,
+    pub change: i64, // 正数表示排名上升
+}
+
+// 全市场排行快照
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MarketRanking {
+    pub symbol_count: usize,
+    pub gainers: HashMap<Interval, Vec<RankEntry>>,
+    pub losers: HashMap<Interval, Vec<RankEntry>>,
+    pub top_turnover: Vec<RankEntry>,
+    pub turnover_rank_changes: Vec<RankChange>,
+}
+
+// 某周期收盘时的市场宽度
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MarketBreadth {
+    pub state: BreadthState,
+    pub symbol_count: usize,
+    pub up_count: usize,
+    pub down_count: usize,
+    pub up_ratio: f64,   // 上涨交易对占比
+    pub down_ratio: f64, // 下跌交易对占比
+    pub avg_return: f64, // 平均涨跌幅
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum BreadthState {
+    Neutral,
+    Bullish, // 普涨
+    Bearish, // 普跌
+}
+
+// 交易对相对 BTC 的强弱
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BtcDivergence {
+    pub symbol_return: f64,
+    pub btc_return: f64,
+    pub beta: f64,     // 滚动窗口内相对 BTC 的 beta
+    pub residual: f64, // 剔除 beta 后的超额收益
+    pub z_score: f64,  // 超额收益相对窗口内波动的倍数
+}
+
+// 上线/下架事件信息
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ListingChange {
+    pub reason: String,         // first_seen / exchange_info / status / not_seen
+    pub status: Option<String>, // exchangeInfo 中的状态
+    pub prev_status: Option<String>,
+    pub first_seen: Option<u64>, // 第一次在 ticker 中出现的时间
+    pub last_seen: Option<u64>,  // 最后一次在 ticker 中出现的时间
+    pub onboard_date: Option<u64>,
 }
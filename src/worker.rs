@@ -1,44 +1,76 @@
 use crate::{
-    config::FundingRateConfig, handlers::trend_handler::{
-        process_consecutive_move, process_funding_rate, process_volatility_spike,
-    }, helper::align_ts, types::{FundingRateLimit, Interval, Kline, Message}
+    config::ConfigReceiver,
+    handlers::{
+        funding_handler::{
+            apply_funding_info, due_countdown, process_funding_countdown,
+            process_funding_interval_change, process_funding_rate, update_funding_schedule,
+        },
+        premium_handler::{detect_premium_deviation, process_premium_deviation},
+        trend_handler::{process_consecutive_move, process_volatility_spike},
+    },
+    helper::{align_ts, annualize_funding_rate, now_ms, rolling_return, DEFAULT_FUNDING_INTERVAL},
+    server::hub::{Hub, KlineUpdate, Push},
+    sink::FanOut,
+    symbols::FilterReceiver,
+    types::{
+        ClosedBar, EventType, FundingRateLimit, FundingSchedule, FundingState, Interval, Kline,
+        MarkPrice, MarketMessage, Message, PremiumState, Query, SymbolSnapshot, SymbolState,
+    },
 };
 use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Arc;
 use tokio::sync::mpsc;
 use tracing::error;
 
-use crate::redis::RedisQueue;
-use std::sync::Arc;
+use crate::dispatcher::EventSender;
+
+// worker 输出的通道
+pub struct WorkerContext {
+    pub market_tx: mpsc::Sender<MarketMessage>, // 全市场聚合器
+    pub events: EventSender,                    // 事件分发器
+    pub hub: Hub,                               // WebSocket 推送
+    pub filter: FilterReceiver,                 // 交易对过滤和检测器开关
+    pub config: ConfigReceiver,                 // 热加载的配置
+    pub sinks: FanOut,                          // 收盘k线写出，回测时为空
+}
 
 // ========== 核心逻辑 ==========
-pub async fn worker(
-    mut rx: mpsc::Receiver<Message>,
-    max_kline_count: u32,
-    funding_rate_config: FundingRateConfig,
-    queue: Arc<RedisQueue>,
-) {
+pub async fn worker(mut rx: mpsc::Receiver<Message>, max_kline_count: u32, ctx: WorkerContext) {
+    let WorkerContext {
+        market_tx,
+        events,
+        hub,
+        filter,
+        config,
+        sinks,
+    } = ctx;
     let mut all_symbols: HashMap<String, HashMap<Interval, Vec<Kline>>> = HashMap::new();
     let mut send_rate: HashMap<String, FundingRateLimit> = HashMap::new();
+    let mut premium_states: HashMap<String, PremiumState> = HashMap::new();
+    let mut funding_schedules: HashMap<String, FundingSchedule> = HashMap::new();
+    // 最新的 ticker 汇总和标记价格，供 REST 接口查询
+    let mut snapshots: HashMap<String, SymbolSnapshot> = HashMap::new();
+    let mut mark_prices: HashMap<String, MarkPrice> = HashMap::new();
+    // fundingInfo 中的结算周期，没有列出的交易对为默认的8小时，加载前为 None
+    let mut funding_info: Option<Arc<HashMap<String, u64>>> = None;
 
     while let Some(msg) = rx.recv().await {
+        // 过滤规则可能被管理接口修改，每条消息取一次最新的
+        let filter = filter.borrow().clone();
+        let cfg = config.borrow().clone();
+        let funding_rate_config = &cfg.funding_rate;
+        let premium_config = &cfg.premium;
         match msg {
             Message::Ticker(t) => {
                 let price: f64 = t.last_price.parse().unwrap_or(0.0);
                 let volume: f64 = t.volume.parse().unwrap_or(0.0);
                 let ts = t.event_time;
 
-                let entry = all_symbols
-                    .entry(t.symbol.clone())
-                    .or_insert_with(HashMap::new);
+                let entry = all_symbols.entry(t.symbol.clone()).or_default();
 
-                for &interval in &[
-                    Interval::Min5,
-                    Interval::Min15,
-                    Interval::Hour1,
-                    Interval::Hour4,
-                ] {
+                for interval in Interval::ALL {
                     let aligned_ts = align_ts(ts, interval);
-                    let klines = entry.entry(interval).or_insert(Vec::new());
+                    let klines = entry.entry(interval).or_default();
 
                     if klines.is_empty() {
                         klines.push(Kline::new(aligned_ts, price, volume));
@@ -48,31 +80,55 @@ pub async fn worker(
                         let closed_klines = klines.clone();
                         let closed_klines2 = klines.clone();
                         let closed_turnover = t.turnover.clone();
-                        let queue_clone = queue.clone();
+                        let events_clone = events.clone();
 
-                        tokio::spawn(async move {
-                            process_volatility_spike(
-                                symbol,
-                                interval,
-                                closed_klines,
-                                closed_turnover,
-                                queue_clone,
-                            )
-                            .await;
-                        });
+                        if filter.enabled(&t.symbol, EventType::VolatilitySpike) {
+                            tokio::spawn(async move {
+                                process_volatility_spike(
+                                    symbol,
+                                    interval,
+                                    closed_klines,
+                                    closed_turnover,
+                                    events_clone,
+                                )
+                                .await;
+                            });
+                        }
 
-                        let queue_clone2 = queue.clone();
+                        let events_clone2 = events.clone();
                         let closed_turnover2 = t.turnover.clone();
-                        tokio::spawn(async move {
-                            process_consecutive_move(
-                                symbol2,
+                        if filter.enabled(&t.symbol, EventType::ConsecutiveMove) {
+                            tokio::spawn(async move {
+                                process_consecutive_move(
+                                    symbol2,
+                                    interval,
+                                    closed_klines2,
+                                    closed_turnover2,
+                                    events_clone2,
+                                )
+                                .await;
+                            });
+                        }
+                        if hub.has_kline_subscribers() {
+                            hub.publish(Push::Kline(Arc::new(KlineUpdate {
+                                symbol: t.symbol.clone(),
                                 interval,
-                                closed_klines2,
-                                closed_turnover2,
-                                queue_clone2,
-                            )
-                            .await;
-                        });
+                                kline: klines.last().unwrap().clone(),
+                                closed: true,
+                            })));
+                        }
+                        let closed_bar = ClosedBar {
+                            symbol: t.symbol.clone(),
+                            interval,
+                            kline: klines.last().unwrap().clone(),
+                        };
+                        // 收盘k线写到各个 sink，供外部服务使用
+                        if filter.allows(&t.symbol) {
+                            sinks.send_kline(&closed_bar, &cfg);
+                        }
+                        // 收盘k线汇总到全市场聚合器，用于市场宽度等跨交易对计算；
+                        // 和 ticker 快照共用通道，队列满时等待而不是丢弃，否则会少算交易对
+                        let _ = market_tx.send(MarketMessage::BarClosed(closed_bar)).await;
                         // 添加新kline
                         klines.push(Kline::new(aligned_ts, price, volume));
                         if klines.len() > max_kline_count as usize {
@@ -81,10 +137,137 @@ pub async fn worker(
                     } else {
                         klines.last_mut().unwrap().update(price, volume);
                     }
+                    // 推送当前k线给 WebSocket 客户端
+                    if hub.has_kline_subscribers() {
+                        hub.publish(Push::Kline(Arc::new(KlineUpdate {
+                            symbol: t.symbol.clone(),
+                            interval,
+                            kline: klines.last().unwrap().clone(),
+                            closed: false,
+                        })));
+                    }
                 }
+
+                // 汇总到全市场聚合器，队列满时直接丢弃，下一个 ticker 会覆盖
+                let returns = Interval::ALL
+                    .iter()
+                    .filter_map(|&interval| {
+                        Some((interval, rolling_return(entry, interval, ts, price)?))
+                    })
+                    .collect();
+                let snapshot = SymbolSnapshot {
+                    symbol: t.symbol.clone(),
+                    event_time: ts,
+                    price,
+                    turnover: t.turnover.parse().unwrap_or(0.0),
+                    returns,
+                };
+                snapshots.insert(t.symbol.clone(), snapshot.clone());
+                let _ = market_tx.try_send(MarketMessage::Snapshot(snapshot));
             }
-            Message::MarkPrice(m) => match m.funding_rate.parse::<f64>() {
-                Ok(funding_rate) if funding_rate.abs() > funding_rate_config.min_funding_rate => {
+            Message::MarkPrice(m) => {
+                let mark_price: f64 = m.mark_price.parse().unwrap_or(0.0);
+                let index_price: f64 = m.index_price.parse().unwrap_or(0.0);
+                // 最新成交价取 5m k线的收盘价
+                let last_price = all_symbols
+                    .get(&m.symbol)
+                    .and_then(|intervals| intervals.get(&Interval::Min5))
+                    .and_then(|klines| klines.last())
+                    .map(|k| k.close);
+                let state = premium_states.entry(m.symbol.clone()).or_default();
+                if let Some((reason, sample)) = detect_premium_deviation(
+                    state,
+                    m.event_time,
+                    mark_price,
+                    index_price,
+                    last_price,
+                    premium_config,
+                )
+                .filter(|_| filter.enabled(&m.symbol, EventType::PremiumDeviation))
+                {
+                    let symbol = m.symbol.clone();
+                    let event_time = m.event_time;
+                    let events_clone = events.clone();
+                    tokio::spawn(async move {
+                        process_premium_deviation(symbol, event_time, reason, sample, events_clone)
+                            .await;
+                    });
+                }
+
+                let funding_rate = match m.funding_rate.parse::<f64>() {
+                    Ok(funding_rate) => funding_rate,
+                    Err(e) => {
+                        // 只跳过资金费率相关的检测，价格仍然保存供查询
+                        error!(
+                            "{} funding_rate parse error {:?}: {}",
+                            m.symbol, m.funding_rate, e
+                        );
+                        mark_prices.insert(m.symbol.clone(), m);
+                        continue;
+                    }
+                };
+
+                // 结算周期跟踪：先使用 fundingInfo 中的周期，之后根据结算时间的变化确认
+                let schedule = funding_schedules
+                    .entry(m.symbol.clone())
+                    .or_insert_with(|| {
+                        let interval = funding_info.as_ref().map(|info| {
+                            info.get(&m.symbol)
+                                .copied()
+                                .unwrap_or(DEFAULT_FUNDING_INTERVAL)
+                        });
+                        FundingSchedule::new(m.next_funding_time, interval)
+                    });
+                if let Some((old_interval, new_interval)) =
+                    update_funding_schedule(schedule, m.next_funding_time)
+                {
+                    if filter.enabled(&m.symbol, EventType::FundingIntervalChange) {
+                        let symbol = m.symbol.clone();
+                        let event_time = m.event_time;
+                        let next_funding_time = m.next_funding_time;
+                        let events_clone = events.clone();
+                        tokio::spawn(async move {
+                            process_funding_interval_change(
+                                symbol,
+                                event_time,
+                                old_interval,
+                                new_interval,
+                                next_funding_time,
+                                events_clone,
+                            )
+                            .await;
+                        });
+                    }
+                }
+                let funding_interval = schedule.interval.unwrap_or(DEFAULT_FUNDING_INTERVAL);
+
+                if filter.enabled(&m.symbol, EventType::FundingCountdown) {
+                    if let Some(minutes) =
+                        due_countdown(schedule, m.event_time, funding_rate, funding_rate_config)
+                    {
+                        let symbol = m.symbol.clone();
+                        let event_time = m.event_time;
+                        let next_funding_time = m.next_funding_time;
+                        let events_clone = events.clone();
+                        let funding_rate = m.funding_rate.clone();
+                        tokio::spawn(async move {
+                            process_funding_countdown(
+                                symbol,
+                                event_time,
+                                funding_rate,
+                                next_funding_time,
+                                funding_interval,
+                                minutes,
+                                events_clone,
+                            )
+                            .await;
+                        });
+                    }
+                }
+
+                if funding_rate.abs() > funding_rate_config.min_funding_rate
+                    && filter.enabled(&m.symbol, EventType::FundingRate)
+                {
                     let changed = match send_rate.entry(m.symbol.clone()) {
                         Entry::Vacant(e) => {
                             e.insert(FundingRateLimit {
@@ -108,7 +291,7 @@ pub async fn worker(
                     };
                     if changed {
                         let symbol = m.symbol.clone();
-                        let queue_clone = queue.clone();
+                        let events_clone = events.clone();
                         let funding_rate = m.funding_rate.clone();
                         tokio::spawn(async move {
                             process_funding_rate(
@@ -116,17 +299,90 @@ pub async fn worker(
                                 m.event_time,
                                 funding_rate,
                                 m.next_funding_time,
-                                queue_clone,
+                                funding_interval,
+                                events_clone,
                             )
                             .await;
                         });
                     }
                 }
-                Ok(_) => {
-                    // debug!("{} Funding rate {}", m.symbol, m.funding_rate);
+                mark_prices.insert(m.symbol.clone(), m);
+            }
+            Message::FundingInfo(info) => {
+                for (symbol, schedule) in funding_schedules.iter_mut() {
+                    let Some((old_interval, new_interval)) =
+                        apply_funding_info(schedule, info.get(symbol).copied())
+                    else {
+                        continue;
+                    };
+                    if filter.enabled(symbol, EventType::FundingIntervalChange) {
+                        let symbol = symbol.clone();
+                        let event_time = mark_prices
+                            .get(&symbol)
+                            .map_or_else(now_ms, |m| m.event_time);
+                        let next_funding_time = schedule.next_funding_time;
+                        let events_clone = events.clone();
+                        tokio::spawn(async move {
+                            process_funding_interval_change(
+                                symbol,
+                                event_time,
+                                old_interval,
+                                new_interval,
+                                next_funding_time,
+                                events_clone,
+                            )
+                            .await;
+                        });
+                    }
+                }
+                funding_info = Some(info);
+            }
+            Message::Query(query) => match query {
+                Query::Symbols(reply) => {
+                    let _ = reply.send(snapshots.values().map(SymbolState::from).collect());
                 }
-                Err(e) => {
-                    error!("funding_rate parse error: {}", e);
+                Query::Klines {
+                    symbol,
+                    interval,
+                    limit,
+                    reply,
+                } => {
+                    let klines = all_symbols
+                        .get(&symbol)
+                        .and_then(|intervals| intervals.get(&interval))
+                        .map(|klines| klines[klines.len().saturating_sub(limit)..].to_vec());
+                    let _ = reply.send(klines);
+                }
+                Query::Funding { symbol, reply } => {
+                    let state = mark_prices.get(&symbol).map(|m| {
+                        let funding_rate = m.funding_rate.parse().unwrap_or(0.0);
+                        let mark_price: f64 = m.mark_price.parse().unwrap_or(0.0);
+                        let index_price: f64 = m.index_price.parse().unwrap_or(0.0);
+                        let interval = funding_schedules.get(&symbol).and_then(|s| s.interval);
+                        let funding_interval = interval.unwrap_or(DEFAULT_FUNDING_INTERVAL);
+                        let avg_premium = premium_states
+                            .get(&symbol)
+                            .filter(|s| !s.samples.is_empty())
+                            .map(|s| s.samples.iter().sum::<f64>() / s.samples.len() as f64);
+                        FundingState {
+                            symbol: symbol.clone(),
+                            event_time: m.event_time,
+                            funding_rate,
+                            annualized_rate: annualize_funding_rate(funding_rate, funding_interval),
+                            next_funding_time: m.next_funding_time,
+                            funding_interval,
+                            interval_confirmed: interval.is_some(),
+                            mark_price,
+                            index_price,
+                            premium: if index_price > 0.0 {
+                                (mark_price - index_price) / index_price
+                            } else {
+                                0.0
+                            },
+                            avg_premium,
+                        }
+                    });
+                    let _ = reply.send(state);
                 }
             },
         }